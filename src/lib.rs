@@ -2,4 +2,8 @@
 
 mod mmv;
 
-pub use mmv::{ActionWhenRenamedFilePathExists, TemplateFileRenamer, TfrError};
+pub use mmv::{
+    ActionWhenRenamedFilePathExists, BackupScheme, ConflictDecision, ConflictResolution, Journal,
+    JournalBatch, MatchKind, MetadataFallback, OperationMode, RenameAction, RenameEntry,
+    RenameNotice, RenamePlan, SortBy, TemplateFileRenamer, TfrError, WhenIdentical,
+};
@@ -1,89 +1,526 @@
-mod mmv;
+mod preview;
 
-use mmv::{ActionWhenRenamedFilePathExists, TemplateFileRenamer};
-
-use crate::mmv::TfrError;
 use chrono::offset::Local;
-use clap::Parser;
+use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
+use preview::print_plan;
+use std::env;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use tfr::{
+    ActionWhenRenamedFilePathExists, BackupScheme, ConflictDecision, ConflictResolution, Journal,
+    MatchKind, MetadataFallback, OperationMode, RenameEntry, RenameNotice, RenamePlan, SortBy,
+    TemplateFileRenamer, TfrError, WhenIdentical,
+};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(group(ArgGroup::new("action_when_exists").args(["force", "skip", "backup", "auto_rename", "update", "larger", "interactive"])))]
+#[command(group(ArgGroup::new("operation_mode").args(["copy", "hardlink", "symlink"])))]
+#[command(group(ArgGroup::new("when_identical").args(["skip_identical", "remove_identical"])))]
+#[command(group(ArgGroup::new("match_kind").args(["dirs", "all"])))]
+#[command(args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Input file path template
     ///
     /// To capture, use the asterisks: '*'.
-    /// Asterisks are allowed only on the last part of the path. Double asterisk are not allowed
+    /// Asterisks are allowed in any part of the path and numbered from left to right.
+    /// Double asterisk '**' used as a whole directory captures zero or more nested directories
     ///
-    /// Example: example/input/template/path_*.*
-    input_file_template: String,
+    /// Example: example/input/template_*/**/path_*.*
+    #[arg(required = true)]
+    input_file_template: Option<String>,
 
     /// Output file path template.
     ///
     /// To insert capture, use flag #<capture_index|int>. Multiple use of the same flag is allowed. All captures must be covered with at least one flag
     ///
-    /// Example: example/output/template/new_#1_path_#1.#2
-    output_file_template: String,
+    /// To change the case of the capture, add a modifier to the flag: #1:upper, #1:lower, #1:title, #1:snake, #1:kebab or #1:camel
+    ///
+    /// To reformat the numeric capture, add a format to the flag: #1:03d pads it with zeros, #1:+10 adds the offset, #1:int strips leading zeros. Modifiers can be chained like #1:+10:03d
+    ///
+    /// To insert the sequence number of the file in the order of matched files, use #n or #{n:start=1,step=1,width=4}, #{n} if a letter or a digit follows. Captures may be left out if the counter or a metadata placeholder below is used
+    ///
+    /// To insert the modification, creation, inode change or access time of the file, use #{mtime:FORMAT}, #{btime:FORMAT}, #{ctime:FORMAT} or #{atime:FORMAT} with the strftime format, %Y-%m-%d by default. Creation (birth) time is replaced with the modification time where it is not available, so is inode change time on other platforms than Unix
+    ///
+    /// To insert EXIF tags of JPEG and TIFF files, use #{exif:TAG}, e.g. #{exif:Model}. Dates are formatted like #{exif:DateTimeOriginal:%Y-%m-%d}
+    ///
+    /// To insert audio tags of MP3, FLAC and OGG files, use #{tag:NAME}: artist, albumartist, album, title, genre, composer, year, track or disc. Track and disc numbers are padded with zeros like #{tag:track:02}
+    ///
+    /// Example: example/output/template/new_#1_path_#1.#2:lower
+    #[arg(required = true)]
+    output_file_template: Option<String>,
 
     /// Use the force flag to overwrite the path to the output file, if it exists
     #[arg(short, long, action)]
     force: bool,
+
+    /// Leave the file in place, if the path to the output file exists
+    #[arg(short, long, action)]
+    skip: bool,
+
+    /// Move the existing output file aside before renaming: to '<path>~' (simple) or to '<path>.~N~' (numbered)
+    #[arg(
+        short,
+        long,
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "simple"
+    )]
+    backup: Option<BackupControl>,
+
+    /// Rename the file to the first free path, if the path to the output file exists.
+    ///
+    /// The pattern of the file name may contain the counter #n, the name #name and the extension #ext
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "#name (#n).#ext"
+    )]
+    auto_rename: Option<String>,
+
+    /// Overwrite the existing output file only if the input file is newer, otherwise leave it in place
+    #[arg(short, long, action)]
+    update: bool,
+
+    /// Overwrite the existing output file only if the input file is larger, otherwise leave it in place
+    #[arg(long, action)]
+    larger: bool,
+
+    /// Prompt what to do, if the path to the output file exists: overwrite, skip, rename to the first free
+    /// path or abort. The answer may be applied to all following existing paths
+    #[arg(short, long, action)]
+    interactive: bool,
+
+    /// Leave the file in place, if the output file exists and has the same content
+    #[arg(long, action)]
+    skip_identical: bool,
+
+    /// Remove the file, if the output file exists and has the same content
+    #[arg(long, action)]
+    remove_identical: bool,
+
+    /// Suffix of simple backups
+    #[arg(short = 'S', long, default_value = "~")]
+    suffix: String,
+
+    /// Copy matched files to the output paths and leave the originals intact
+    #[arg(short, long, action)]
+    copy: bool,
+
+    /// Create hard links to matched files at the output paths
+    #[arg(long, action)]
+    hardlink: bool,
+
+    /// Create symbolic links with absolute paths to matched files at the output paths
+    #[arg(long, action)]
+    symlink: bool,
+
+    /// Make symbolic links relative to the directories of the output paths
+    #[arg(long, action, requires = "symlink")]
+    relative: bool,
+
+    /// Match only directories. Matched directories are moved with all their content
+    #[arg(short, long, action)]
+    dirs: bool,
+
+    /// Match both files and directories
+    #[arg(short, long, action)]
+    all: bool,
+
+    /// Merge matched directories into existing output directories. Existing files are handled
+    /// according to --force, --skip or --backup
+    #[arg(short, long, action)]
+    merge: bool,
+
+    /// Order of matched files, followed by the counter and the output. Natural order compares numbers by
+    /// their values, so 'img2' precedes 'img10'
+    #[arg(long, value_enum, default_value = "natural")]
+    sort: SortKey,
+
+    /// Reverse the order of matched files
+    #[arg(short, long, action)]
+    reverse: bool,
+
+    /// What is inserted instead of a metadata placeholder like #{exif:Model} or #{tag:artist}, if the file lacks the tag: the file is skipped, or the modification time is inserted
+    #[arg(long, value_enum, default_value = "skip")]
+    fallback: FallbackControl,
+
+    /// Literal value inserted instead of a metadata placeholder, if the file lacks the tag
+    #[arg(long, value_name = "VALUE", conflicts_with = "fallback")]
+    fallback_value: Option<String>,
+
+    /// Revert all performed renames if any error occurs
+    #[arg(short, long, action)]
+    transactional: bool,
+
+    /// Print the files that would be renamed without renaming them
+    #[arg(short = 'n', long, action)]
+    dry_run: bool,
+
+    /// Directory of the journal used to undo renaming.
+    ///
+    /// Defaults to $TFR_STATE_DIR, $XDG_STATE_HOME/tfr or ~/.local/state/tfr
+    #[arg(long, global = true)]
+    state_dir: Option<PathBuf>,
+
+    /// Rename without recording the batch to the journal, so it can not be undone
+    #[arg(long, action)]
+    no_journal: bool,
+
+    /// Save overwritten and removed files in the state directory, so undo restores them. They are deleted by default
+    #[arg(long, action, conflicts_with = "no_journal")]
+    keep_overwritten: bool,
+
+    /// Number of the latest batches kept in the journal, older batches and their saved files are removed
+    #[arg(long, value_name = "N", default_value_t = 20, value_parser = clap::value_parser!(u64).range(1..))]
+    keep_batches: u64,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum BackupControl {
+    Simple,
+    Numbered,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum SortKey {
+    Name,
+    Natural,
+    Mtime,
+    /// Creation (birth) time, the modification time is used where it is not available
+    Btime,
+    /// Inode change time on Unix, the modification time is used on other platforms
+    Ctime,
+    Size,
+}
+
+impl From<SortKey> for SortBy {
+    fn from(sort_key: SortKey) -> Self {
+        match sort_key {
+            SortKey::Name => SortBy::Name,
+            SortKey::Natural => SortBy::Natural,
+            SortKey::Mtime => SortBy::Modified,
+            SortKey::Btime => SortBy::Created,
+            SortKey::Ctime => SortBy::Changed,
+            SortKey::Size => SortBy::Size,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum FallbackControl {
+    Skip,
+    Mtime,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Revert renaming recorded in the journal. Files changed since renaming are not reverted
+    ///
+    /// Files overwritten during renaming are restored only if they have been saved with --keep-overwritten. Saved
+    /// files take disk space in the state directory until the batch is undone or dropped from the journal, only
+    /// the latest --keep-batches batches are kept. If the state directory is on another filesystem, overwritten
+    /// files are copied there
+    Undo {
+        /// Id of the batch to revert. The last batch is reverted by default
+        batch_id: Option<String>,
+    },
+}
+
+fn print_error(tfr_error: TfrError) {
+    match tfr_error {
+        TfrError::IncorrectInputTemplate(description) => {
+            eprintln!("IncorrectInputTemplate error occurred: {description}")
+        }
+        TfrError::IncorrectOutputTemplate(description) => {
+            eprintln!("IncorrectOutputTemplate error occurred: {description}")
+        }
+        TfrError::ExistingPath(existing_filepath, is_file) => {
+            eprintln!(
+                "Not able to replace existing {}: {}",
+                if is_file { "file" } else { "path" },
+                existing_filepath
+            )
+        }
+        TfrError::DestinationCollision(destination, sources) => {
+            eprintln!(
+                "Several files are renamed to the same path {}: {}",
+                destination,
+                sources.join(", ")
+            )
+        }
+        TfrError::RolledBack(error, rollback_errors) => {
+            print_error(*error);
+            match rollback_errors.is_empty() {
+                true => eprintln!("All performed renames have been reverted"),
+                false => {
+                    eprintln!("Not all performed renames have been reverted:");
+                    for rollback_error in rollback_errors {
+                        eprintln!("  {rollback_error}")
+                    }
+                }
+            }
+        }
+        TfrError::JournalBatchNotFound(batch_id) => match batch_id {
+            None => eprintln!("Journal is empty, nothing to undo"),
+            Some(batch_id) => eprintln!("Batch {batch_id} not found in the journal"),
+        },
+        TfrError::ChangedSinceRename(path) => {
+            eprintln!("Not able to undo renaming, path was changed after renaming: {path}")
+        }
+        TfrError::NotNumericCapture(path, capture) => {
+            eprintln!("Not able to format '{capture}' of {path} as a number")
+        }
+        TfrError::MissingMetadata(path, placeholder) => {
+            eprintln!("Not able to read {placeholder} of {path}")
+        }
+        TfrError::Aborted => eprintln!("Renaming aborted"),
+        TfrError::StdError(error) => {
+            eprintln!("Some error occurred: {:?}", error.as_ref())
+        }
+    }
+}
+
+impl Args {
+    fn journal(&self) -> Option<Journal> {
+        self.state_dir
+            .clone()
+            .or_else(|| env::var_os("TFR_STATE_DIR").map(PathBuf::from))
+            .or_else(|| env::var_os("XDG_STATE_HOME").map(|state| PathBuf::from(state).join("tfr")))
+            .or_else(|| {
+                env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state/tfr"))
+            })
+            .map(|directory| {
+                let mut journal = Journal::new(directory);
+                journal.set_max_batches(self.keep_batches as usize);
+                journal.set_keep_overwritten(self.keep_overwritten);
+                journal
+            })
+    }
+
+    fn operation_mode(&self) -> OperationMode {
+        if self.copy {
+            OperationMode::Copy
+        } else if self.hardlink {
+            OperationMode::HardLink
+        } else if self.symlink {
+            OperationMode::SymLink {
+                relative: self.relative,
+            }
+        } else {
+            OperationMode::Move
+        }
+    }
+
+    fn match_kind(&self) -> MatchKind {
+        if self.dirs {
+            MatchKind::Directories
+        } else if self.all {
+            MatchKind::All
+        } else {
+            MatchKind::Files
+        }
+    }
+
+    fn backup_scheme(&self) -> BackupScheme {
+        match self.backup {
+            Some(BackupControl::Numbered) => BackupScheme::Numbered,
+            _ => BackupScheme::Simple {
+                suffix: self.suffix.clone(),
+            },
+        }
+    }
+
+    fn metadata_fallback(&self) -> MetadataFallback {
+        match (&self.fallback_value, &self.fallback) {
+            (Some(value), _) => MetadataFallback::Default(value.clone()),
+            (None, FallbackControl::Skip) => MetadataFallback::Skip,
+            (None, FallbackControl::Mtime) => MetadataFallback::Modified,
+        }
+    }
+
+    fn when_identical(&self) -> WhenIdentical {
+        if self.skip_identical {
+            WhenIdentical::Skip
+        } else if self.remove_identical {
+            WhenIdentical::RemoveSource
+        } else {
+            WhenIdentical::Ignore
+        }
+    }
+
+    fn action_when_exists(&self) -> ActionWhenRenamedFilePathExists {
+        if self.force {
+            ActionWhenRenamedFilePathExists::Overwrite
+        } else if self.skip {
+            ActionWhenRenamedFilePathExists::Skip
+        } else if self.backup.is_some() {
+            ActionWhenRenamedFilePathExists::Backup
+        } else if self.auto_rename.is_some() {
+            ActionWhenRenamedFilePathExists::AutoRename
+        } else if self.update {
+            ActionWhenRenamedFilePathExists::OverwriteIfNewer
+        } else if self.larger {
+            ActionWhenRenamedFilePathExists::OverwriteIfLarger
+        } else if self.interactive {
+            ActionWhenRenamedFilePathExists::Ask
+        } else {
+            ActionWhenRenamedFilePathExists::Terminate
+        }
+    }
+}
+
+/// Asks on the terminal what to do with the file renamed to the existing path. End of input aborts renaming
+fn ask_conflict(source: &str, destination: &str) -> ConflictDecision {
+    loop {
+        eprint!(
+            "{destination} exists, renaming {source}. \
+            [o]verwrite, [s]kip, [r]ename, [a]bort (uppercase O, S, R apply to all): "
+        );
+        let mut answer = String::new();
+        if std::io::stdin().read_line(&mut answer).unwrap_or(0) == 0 {
+            eprintln!();
+            return ConflictDecision::once(ConflictResolution::Abort);
+        }
+        let resolution = match answer.trim().to_lowercase().as_str() {
+            "o" => ConflictResolution::Overwrite,
+            "s" => ConflictResolution::Skip,
+            "r" => ConflictResolution::Rename,
+            "a" => ConflictResolution::Abort,
+            _ => continue,
+        };
+        return ConflictDecision {
+            resolution,
+            apply_to_all: answer.trim().chars().all(char::is_uppercase),
+        };
+    }
+}
+
+fn plan_or_exit(
+    tfr: &TemplateFileRenamer,
+    input_file_template: &str,
+    output_file_template: &str,
+) -> RenamePlan {
+    let plan = tfr
+        .plan(input_file_template, output_file_template)
+        .unwrap_or_else(|tfr_error| {
+            print_error(tfr_error);
+            std::process::exit(1);
+        });
+    if plan.entries().is_empty() {
+        println!("Files for pattern '{input_file_template}' not found");
+        std::process::exit(1);
+    }
+    plan
+}
+
+fn undo(journal: Option<Journal>, batch_id: Option<&str>) {
+    let Some(journal) = journal else {
+        eprintln!("Journal directory is not set, use --state-dir");
+        std::process::exit(1);
+    };
+    match journal.undo(batch_id) {
+        Ok(batch) => {
+            for (source, destination) in &batch.renamed {
+                println!("{destination} -> {source}")
+            }
+            for file in batch.deleted_files() {
+                println!("{file} was deleted during renaming and is not restored")
+            }
+            println!("Batch {} from {} is reverted", batch.id, batch.time)
+        }
+        Err(tfr_error) => {
+            print_error(tfr_error);
+            std::process::exit(1);
+        }
+    }
 }
 
 fn main() {
     let args = Args::parse();
+    let journal = args.journal();
+    if let Some(Command::Undo { batch_id }) = &args.command {
+        return undo(journal, batch_id.as_deref());
+    }
+    let action_when_exists = args.action_when_exists();
+    let backup_scheme = args.backup_scheme();
+    let when_identical = args.when_identical();
+    let operation_mode = args.operation_mode();
+    let match_kind = args.match_kind();
+    let metadata_fallback = args.metadata_fallback();
 
-    let input_file_template = args.input_file_template;
-    let output_file_template = args.output_file_template;
+    let journal = journal.filter(|_| !args.no_journal);
+    let input_file_template = args.input_file_template.unwrap();
+    let output_file_template = args.output_file_template.unwrap();
+
+    let mut tfr = TemplateFileRenamer::new(action_when_exists);
+    tfr.set_backup_scheme(backup_scheme);
+    tfr.set_when_identical(when_identical);
+    if let Some(auto_rename_pattern) = &args.auto_rename {
+        tfr.set_auto_rename_pattern(auto_rename_pattern);
+    }
+    tfr.set_operation_mode(operation_mode);
+    tfr.set_match_kind(match_kind);
+    tfr.set_merge_directories(args.merge);
+    tfr.set_sort_by(args.sort.clone().into());
+    tfr.set_reverse_order(args.reverse);
+    tfr.set_metadata_fallback(metadata_fallback);
+    tfr.set_transactional(args.transactional);
+    tfr.set_conflict_handler(ask_conflict);
+    if let Some(journal) = journal.clone() {
+        tfr.set_journal(journal);
+    }
+    let plan = plan_or_exit(&tfr, &input_file_template, &output_file_template);
+    if args.dry_run {
+        print_plan(&plan, std::io::stdout().is_terminal());
+        if plan.conflicts().next().is_some() {
+            std::process::exit(1);
+        }
+        return;
+    }
 
     let start_time = Local::now();
     let callback_handler =
         |processed: usize, total: usize, old_filepath: Option<&str>, new_filepath: Option<&str>| {
             if processed == 0 {
-                return match total {
-                    0 => {
-                        println!("Files for pattern '{input_file_template}' not found");
-                        std::process::exit(1);
-                    }
-                    _ => {
-                        println!(
-                            "Started with params: {} -> {}. Files to rename: {}",
-                            input_file_template, output_file_template, total
-                        )
-                    }
-                };
+                return println!(
+                    "Started with params: {} -> {}. Files to rename: {}",
+                    input_file_template, output_file_template, total
+                );
             }
 
             println!("{} -> {}", old_filepath.unwrap(), new_filepath.unwrap());
-
-            if processed == total {
-                println!(
-                    "Finished in {}ms.",
-                    (Local::now() - start_time).num_milliseconds()
-                )
-            }
         };
-    let mut tfr = TemplateFileRenamer::new(ActionWhenRenamedFilePathExists::Terminate);
     tfr.set_callback_handler(callback_handler);
-
-    if let Err(tfr_error) = tfr.rename(&input_file_template, &output_file_template) {
-        match tfr_error {
-            TfrError::IncorrectInputTemplate(description) => {
-                eprintln!("IncorrectInputTemplate error occurred: {description}")
-            }
-            TfrError::IncorrectOutputTemplate(description) => {
-                eprintln!("IncorrectOutputTemplate error occurred: {description}")
-            }
-            TfrError::ExistingPath(existing_filepath, is_file) => {
-                eprintln!(
-                    "Not able to replace existing {}: {}",
-                    if is_file { "file" } else { "path" },
-                    existing_filepath
-                )
-            }
-            TfrError::StdError(error) => {
-                eprintln!("Some error occurred: {:?}", error.as_ref())
-            }
+    tfr.set_notice_handler(|notice: RenameNotice| match notice {
+        RenameNotice::CrossDeviceCopy { from, to } => {
+            println!("Copying {from} to {to} on another filesystem, it may take a while")
         }
+        RenameNotice::BackedUp { path, backup } => println!("Backed up {path} to {backup}"),
+        RenameNotice::RemovedIdentical { path, identical } => {
+            println!("Removed {path}, identical to {identical}")
+        }
+        _ => {}
+    });
+
+    if let Err(tfr_error) = tfr.execute(&plan) {
+        print_error(tfr_error);
         std::process::exit(1);
     }
+    println!(
+        "Finished in {}ms.",
+        (Local::now() - start_time).num_milliseconds()
+    );
+
+    let is_changed = plan.entries().iter().any(RenameEntry::frees_source);
+    let last_batch = journal.and_then(|journal| journal.batches().ok()?.pop());
+    if let Some(batch) = last_batch.filter(|_| is_changed) {
+        println!("To revert, run: tfr undo {}", batch.id)
+    }
 }
@@ -0,0 +1,146 @@
+use std::path::Path;
+
+/// Pattern of free paths chosen in the `AutoRename` mod: `photo.jpg` becomes `photo (1).jpg`
+pub const DEFAULT_AUTO_RENAME_PATTERN: &str = "#name (#n).#ext";
+
+/// Part of the auto rename pattern
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Token<'p> {
+    Literal(&'p str),
+    /// `#name`
+    Name,
+    /// `#ext`
+    Extension,
+    /// `#n`, not followed by a letter or a digit
+    Counter,
+}
+
+fn tokens(pattern: &str) -> Vec<Token<'_>> {
+    let mut tokens = vec![];
+    let mut literal_start = 0;
+    let mut position = 0;
+    while let Some(offset) = pattern[position..].find('#') {
+        let start = position + offset;
+        let rest = &pattern[start..];
+        let is_counter =
+            rest.starts_with("#n") && !rest[2..].starts_with(|ch: char| ch.is_ascii_alphanumeric());
+        let (token, length) = if rest.starts_with("#name") {
+            (Some(Token::Name), 5)
+        } else if rest.starts_with("#ext") {
+            (Some(Token::Extension), 4)
+        } else if is_counter {
+            (Some(Token::Counter), 2)
+        } else {
+            (None, 1)
+        };
+        if let Some(token) = token {
+            if literal_start < start {
+                tokens.push(Token::Literal(&pattern[literal_start..start]));
+            }
+            tokens.push(token);
+            literal_start = start + length;
+        }
+        position = start + length;
+    }
+    if literal_start < pattern.len() {
+        tokens.push(Token::Literal(&pattern[literal_start..]));
+    }
+    tokens
+}
+
+/// Returns true if the pattern contains the counter, so it produces different names
+pub fn is_auto_rename_pattern_correct(pattern: &str) -> bool {
+    tokens(pattern).contains(&Token::Counter)
+}
+
+/// Applies the pattern to the file name of the path. `#name` is replaced with the file name without
+/// the extension, `#ext` with the extension and `#n` with the counter. If the file has no extension,
+/// `.#ext` is omitted. The pattern is filled in a single pass, so `#` in the file name is kept as is
+pub fn auto_rename_path(path: &str, pattern: &str, counter: usize) -> String {
+    let path = Path::new(path);
+    let name = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy());
+    let tokens = tokens(pattern);
+    let mut file_name = String::new();
+    for (idx, token) in tokens.iter().enumerate() {
+        match (token, &extension) {
+            (Token::Literal(text), _) => {
+                let is_before_missing_extension =
+                    extension.is_none() && tokens.get(idx + 1) == Some(&Token::Extension);
+                match is_before_missing_extension {
+                    true => file_name.push_str(text.strip_suffix('.').unwrap_or(text)),
+                    false => file_name.push_str(text),
+                }
+            }
+            (Token::Name, _) => file_name.push_str(&name),
+            (Token::Extension, Some(extension)) => file_name.push_str(extension),
+            (Token::Extension, None) => {}
+            (Token::Counter, _) => file_name.push_str(&counter.to_string()),
+        }
+    }
+
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => {
+            parent.join(file_name).to_string_lossy().to_string()
+        }
+        _ => file_name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_pattern_test() {
+        assert_eq!(
+            auto_rename_path("dir/photo.jpg", DEFAULT_AUTO_RENAME_PATTERN, 1),
+            "dir/photo (1).jpg"
+        );
+        assert_eq!(
+            auto_rename_path("photo.tar.gz", DEFAULT_AUTO_RENAME_PATTERN, 12),
+            "photo.tar (12).gz"
+        );
+        assert_eq!(
+            auto_rename_path("dir/README", DEFAULT_AUTO_RENAME_PATTERN, 2),
+            "dir/README (2)"
+        );
+    }
+
+    #[test]
+    fn custom_pattern_test() {
+        assert_eq!(
+            auto_rename_path("dir/photo.jpg", "#name_#n.#ext", 3),
+            "dir/photo_3.jpg"
+        );
+        assert_eq!(
+            auto_rename_path("photo.jpg", "#n-#name.#ext", 1),
+            "1-photo.jpg"
+        );
+    }
+
+    #[test]
+    fn hash_in_file_name_test() {
+        assert_eq!(
+            auto_rename_path("out/x#n.txt", DEFAULT_AUTO_RENAME_PATTERN, 1),
+            "out/x#n (1).txt"
+        );
+        assert_eq!(
+            auto_rename_path("#name#ext.#n", "#name_#n.#ext", 2),
+            "#name#ext_2.#n"
+        );
+        assert_eq!(auto_rename_path("a", "#next_#n.#ext", 3), "#next_3");
+    }
+
+    #[test]
+    fn pattern_correctness_test() {
+        assert!(is_auto_rename_pattern_correct(DEFAULT_AUTO_RENAME_PATTERN));
+        assert!(is_auto_rename_pattern_correct("#name_#n"));
+        assert!(!is_auto_rename_pattern_correct("#name.#ext"));
+        assert!(!is_auto_rename_pattern_correct("copy of #name"));
+        assert!(!is_auto_rename_pattern_correct("#next.#ext"));
+        assert!(is_auto_rename_pattern_correct("#n1_#n"));
+    }
+}
@@ -0,0 +1,99 @@
+use std::fs;
+use std::path::Path;
+
+/// Naming of backups made in the `Backup` mod, like `mv --backup` does
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum BackupScheme {
+    /// The existing file is moved to `<path><suffix>`, the previous backup is replaced
+    Simple { suffix: String },
+    /// The existing file is moved to `<path>.~N~`, where N is greater than numbers of all existing backups,
+    /// so backups are never replaced
+    Numbered,
+}
+
+impl Default for BackupScheme {
+    fn default() -> Self {
+        BackupScheme::Simple {
+            suffix: "~".to_string(),
+        }
+    }
+}
+
+/// Returns the number following the greatest number of `<file_name>.~N~` backups among `names`
+fn next_backup_number<'a>(file_name: &str, names: impl Iterator<Item = &'a str>) -> usize {
+    names
+        .filter_map(|name| {
+            name.strip_prefix(file_name)?
+                .strip_prefix(".~")?
+                .strip_suffix('~')?
+                .parse::<usize>()
+                .ok()
+        })
+        .max()
+        .unwrap_or(0)
+        + 1
+}
+
+impl BackupScheme {
+    /// Path where the existing file at `path` is moved
+    pub fn backup_path(&self, path: &str) -> String {
+        match self {
+            BackupScheme::Simple { suffix } => format!("{path}{suffix}"),
+            BackupScheme::Numbered => {
+                let path = Path::new(path);
+                let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+                let parent = match path.parent() {
+                    Some(parent) if !parent.as_os_str().is_empty() => parent,
+                    _ => Path::new("."),
+                };
+                let names: Vec<String> = fs::read_dir(parent)
+                    .map(|entries| {
+                        entries
+                            .filter_map(|entry| entry.ok())
+                            .map(|entry| entry.file_name().to_string_lossy().to_string())
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                let number = next_backup_number(&file_name, names.iter().map(String::as_str));
+                format!("{}.~{number}~", path.to_string_lossy())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_backup_number_test() {
+        assert_eq!(next_backup_number("a.txt", [].into_iter()), 1);
+        assert_eq!(
+            next_backup_number("a.txt", ["a.txt", "a.txt~", "b.txt.~3~"].into_iter()),
+            1
+        );
+        assert_eq!(
+            next_backup_number(
+                "a.txt",
+                ["a.txt.~1~", "a.txt.~10~", "a.txt.~2~"].into_iter()
+            ),
+            11
+        );
+        assert_eq!(
+            next_backup_number("a.txt", ["a.txt.~x~", "a.txt.~2~.bak"].into_iter()),
+            1
+        );
+    }
+
+    #[test]
+    fn simple_test() {
+        assert_eq!(
+            BackupScheme::default().backup_path("dir/a.txt"),
+            "dir/a.txt~"
+        );
+        let scheme = BackupScheme::Simple {
+            suffix: ".bak".to_string(),
+        };
+        assert_eq!(scheme.backup_path("a.txt"), "a.txt.bak");
+    }
+}
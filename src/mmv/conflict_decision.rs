@@ -0,0 +1,39 @@
+/// Resolution of a conflict with an existing path chosen in the `Ask` mod
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ConflictResolution {
+    /// The existing file is replaced with the renamed file
+    Overwrite,
+    /// The file is left in place
+    Skip,
+    /// The file is renamed to the first free path given by the
+    /// [auto rename pattern](crate::TemplateFileRenamer::set_auto_rename_pattern)
+    Rename,
+    /// Planning is stopped with [TfrError::Aborted](crate::TfrError::Aborted), nothing is renamed
+    Abort,
+}
+
+/// Answer of the [conflict handler](crate::TemplateFileRenamer::set_conflict_handler)
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ConflictDecision {
+    pub resolution: ConflictResolution,
+    /// The resolution is applied to all following conflicts without calling the handler
+    pub apply_to_all: bool,
+}
+
+impl ConflictDecision {
+    /// Decision for the current conflict only
+    pub fn once(resolution: ConflictResolution) -> Self {
+        Self {
+            resolution,
+            apply_to_all: false,
+        }
+    }
+
+    /// Decision for the current and all following conflicts
+    pub fn for_all(resolution: ConflictResolution) -> Self {
+        Self {
+            resolution,
+            apply_to_all: true,
+        }
+    }
+}
@@ -6,25 +6,43 @@ use crate::mmv::TfrError::IncorrectInputTemplate;
 /// - `IncorrectInputTemplate` and `IncorrectOutputTemplate` occur when the passed templates are incorrect.
 /// - `ExistingPath` occurs when the renaming mod is terminated if an existing path is found or existing path
 ///   is something except file
+/// - `DestinationCollision` occurs when the renaming mod is terminated if several matched files are renamed to
+///   the same path. All colliding source paths are saved
+/// - `RolledBack` occurs in the transactional mode when renaming failed. It contains the original error and errors
+///   occurred while reverting already performed renames (empty if everything has been reverted)
+/// - `JournalBatchNotFound` occurs when the batch to undo is not found in the journal (None id means the last batch)
+/// - `ChangedSinceRename` occurs when the batch can not be undone, because the path was changed after renaming
+/// - `NotNumericCapture` occurs when a number format of the output template is applied to a capture of the
+///   path that is not an integer, or the counter overflows. The capture is the counter placeholder then
+/// - `MissingMetadata` occurs when the placeholder of the output template can not be resolved, because the
+///   metadata of the path is not available
+/// - `Aborted` occurs in the `Ask` mod when the conflict handler aborts renaming, nothing is renamed
 /// - Other errors ([std::error::Error](std::error::Error)) saved in 'StdError'. It was expected that only errors from
-/// [fs::rename](std::fs::rename) can be occur here.
+///   [fs::rename](std::fs::rename) can be occur here.
 #[derive(Debug)]
 pub enum TfrError {
     IncorrectInputTemplate(&'static str),
     IncorrectOutputTemplate(&'static str),
     ExistingPath(/*path=*/ String, /*is_file=*/ bool),
+    DestinationCollision(/*destination=*/ String, /*sources=*/ Vec<String>),
+    RolledBack(
+        /*error=*/ Box<TfrError>,
+        /*rollback_errors=*/ Vec<std::io::Error>,
+    ),
+    JournalBatchNotFound(/*id=*/ Option<String>),
+    ChangedSinceRename(/*path=*/ String),
+    NotNumericCapture(/*path=*/ String, /*capture=*/ String),
+    MissingMetadata(/*path=*/ String, /*placeholder=*/ String),
+    Aborted,
     StdError(Box<dyn std::error::Error>),
 }
 
 impl From<TemplateError> for TfrError {
     fn from(template_err: TemplateError) -> Self {
         match template_err {
-            TemplateError::AsteriskInDirectory => {
-                IncorrectInputTemplate("Found asterisks in directory of input template")
-            }
-            TemplateError::DoubleAsterisk => {
-                IncorrectInputTemplate("Found double asterisk in input template")
-            }
+            TemplateError::DoubleAsterisk => IncorrectInputTemplate(
+                "Double asterisk is allowed only as a whole directory of input template",
+            ),
         }
     }
 }
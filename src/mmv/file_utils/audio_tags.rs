@@ -0,0 +1,372 @@
+use std::fs::File;
+use std::io::Read;
+
+/// Vorbis comments are located at the beginning of FLAC and OGG files, the rest of the file is not read
+const VORBIS_HEADER_LIMIT: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum AudioTagKind {
+    Text,
+    /// Number like `3` or `3/12`, the total is dropped
+    Number,
+    /// Year or date like `2023-05-07`, only the year is kept
+    Year,
+}
+
+/// Audio tag inserted by `#{tag:name}` or `#{tag:name:width}` for zero-padded numbers
+#[derive(Debug, Eq, PartialEq)]
+pub struct AudioTag {
+    pub name: &'static str,
+    /// ID3v2.3 and ID3v2.4 frames of the tag, the first found frame is used
+    id3_frames: &'static [&'static str],
+    vorbis_key: &'static str,
+    kind: AudioTagKind,
+}
+
+const fn tag(
+    name: &'static str,
+    id3_frames: &'static [&'static str],
+    vorbis_key: &'static str,
+    kind: AudioTagKind,
+) -> AudioTag {
+    AudioTag {
+        name,
+        id3_frames,
+        vorbis_key,
+        kind,
+    }
+}
+
+const AUDIO_TAGS: &[AudioTag] = &[
+    tag("artist", &["TPE1"], "ARTIST", AudioTagKind::Text),
+    tag("albumartist", &["TPE2"], "ALBUMARTIST", AudioTagKind::Text),
+    tag("album", &["TALB"], "ALBUM", AudioTagKind::Text),
+    tag("title", &["TIT2"], "TITLE", AudioTagKind::Text),
+    tag("genre", &["TCON"], "GENRE", AudioTagKind::Text),
+    tag("composer", &["TCOM"], "COMPOSER", AudioTagKind::Text),
+    tag("track", &["TRCK"], "TRACKNUMBER", AudioTagKind::Number),
+    tag("disc", &["TPOS"], "DISCNUMBER", AudioTagKind::Number),
+    tag("year", &["TDRC", "TYER"], "DATE", AudioTagKind::Year),
+];
+
+impl AudioTag {
+    pub fn from_name(name: &str) -> Option<&'static AudioTag> {
+        AUDIO_TAGS.iter().find(|tag| tag.name == name)
+    }
+
+    /// Returns true if the value is a number that can be zero-padded
+    pub fn is_number(&self) -> bool {
+        self.kind == AudioTagKind::Number
+    }
+
+    fn normalized(&self, value: &str) -> Option<String> {
+        let value = match self.kind {
+            AudioTagKind::Text => value,
+            AudioTagKind::Number => value.split('/').next()?.trim(),
+            AudioTagKind::Year => value.get(..4).filter(|year| year.parse::<u16>().is_ok())?,
+        };
+        (!value.is_empty()).then(|| value.to_string())
+    }
+}
+
+fn syncsafe(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .fold(0, |size, &byte| (size << 7) | (byte & 0x7F) as usize)
+}
+
+/// Decodes the text of ID3v2 text frames, only the first of several values is returned
+fn decode_id3_text(data: &[u8]) -> Option<String> {
+    let (&encoding, text) = data.split_first()?;
+    let utf16 = |text: &[u8], is_big_endian: bool| {
+        let units: Vec<u16> = text
+            .chunks_exact(2)
+            .map(|unit| match is_big_endian {
+                true => u16::from_be_bytes([unit[0], unit[1]]),
+                false => u16::from_le_bytes([unit[0], unit[1]]),
+            })
+            .collect();
+        String::from_utf16_lossy(&units)
+    };
+    let text = match encoding {
+        // ISO-8859-1
+        0 => text.iter().map(|&byte| byte as char).collect(),
+        // UTF-16 with BOM
+        1 => match text {
+            [0xFF, 0xFE, rest @ ..] => utf16(rest, false),
+            [0xFE, 0xFF, rest @ ..] => utf16(rest, true),
+            _ => utf16(text, false),
+        },
+        // UTF-16BE
+        2 => utf16(text, true),
+        3 => String::from_utf8_lossy(text).into_owned(),
+        _ => return None,
+    };
+    Some(text.split('\0').next()?.trim().to_string())
+}
+
+/// Returns the text of the first of `frames` from the ID3v2.3 or ID3v2.4 tag at the beginning of `data`
+fn parse_id3v2(data: &[u8], frames: &[&str]) -> Option<String> {
+    let header = data.get(..10).filter(|header| header.starts_with(b"ID3"))?;
+    let version = header[3];
+    if version != 3 && version != 4 {
+        return None;
+    }
+    let tag_end = (10 + syncsafe(&header[6..10])).min(data.len());
+    let mut position = 10;
+    if header[5] & 0x40 != 0 {
+        // Extended header
+        let size = data.get(position..position + 4)?;
+        position += match version {
+            3 => 4 + u32::from_be_bytes(size.try_into().ok()?) as usize,
+            _ => syncsafe(size),
+        };
+    }
+
+    let mut found: Vec<(&[u8], &[u8])> = vec![];
+    while position + 10 <= tag_end {
+        let frame_header = &data[position..position + 10];
+        if frame_header[0] == 0 {
+            // Padding
+            break;
+        }
+        let size = match version {
+            3 => u32::from_be_bytes(frame_header[4..8].try_into().ok()?) as usize,
+            _ => syncsafe(&frame_header[4..8]),
+        };
+        let mut body = data.get(position + 10..(position + 10 + size).min(tag_end))?;
+        if version == 4 && frame_header[9] & 0x01 != 0 {
+            // Data length indicator
+            body = body.get(4..)?;
+        }
+        found.push((&frame_header[..4], body));
+        position += 10 + size;
+    }
+    frames.iter().find_map(|frame| {
+        let (_, body) = found.iter().find(|(id, _)| *id == frame.as_bytes())?;
+        decode_id3_text(body)
+    })
+}
+
+fn u32_le(data: &[u8], position: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(position..position + 4)?.try_into().ok()?,
+    ))
+}
+
+/// Returns the value of the key from the Vorbis comment structure, keys are case-insensitive.
+/// Comments following the truncated one are ignored
+fn parse_vorbis_comment(data: &[u8], key: &str) -> Option<String> {
+    let vendor_length = u32_le(data, 0)? as usize;
+    let mut position = 4 + vendor_length;
+    let count = u32_le(data, position)?;
+    position += 4;
+    for _ in 0..count {
+        let length = u32_le(data, position)? as usize;
+        let comment = data.get(position + 4..position + 4 + length)?;
+        position += 4 + length;
+        let comment = String::from_utf8_lossy(comment);
+        if let Some((comment_key, value)) = comment.split_once('=') {
+            if comment_key.eq_ignore_ascii_case(key) {
+                return Some(value.trim().to_string());
+            }
+        }
+    }
+    None
+}
+
+/// Returns the value of the key from the VORBIS_COMMENT block of the FLAC file content
+fn parse_flac(data: &[u8], key: &str) -> Option<String> {
+    let mut position = 4;
+    loop {
+        let header = data.get(position..position + 4)?;
+        let is_last = header[0] & 0x80 != 0;
+        let block_type = header[0] & 0x7F;
+        let length = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+        if block_type == 4 {
+            let end = (position + 4 + length).min(data.len());
+            return parse_vorbis_comment(&data[position + 4..end], key);
+        }
+        if is_last {
+            return None;
+        }
+        position += 4 + length;
+    }
+}
+
+/// Returns the value of the key from the comment header of the Vorbis or Opus stream of the OGG file content
+fn parse_ogg(data: &[u8], key: &str) -> Option<String> {
+    // Packets of a single stream are the concatenation of page bodies
+    let mut packets = vec![];
+    let mut position = 0;
+    while let Some(header) = data.get(position..position + 27) {
+        if !header.starts_with(b"OggS") {
+            break;
+        }
+        let segments_count = header[26] as usize;
+        let Some(segments) = data.get(position + 27..position + 27 + segments_count) else {
+            break;
+        };
+        let body_start = position + 27 + segments_count;
+        let body_end = body_start + segments.iter().map(|&size| size as usize).sum::<usize>();
+        packets.extend_from_slice(&data[body_start..body_end.min(data.len())]);
+        position = body_end;
+    }
+    [b"\x03vorbis".as_slice(), b"OpusTags".as_slice()]
+        .iter()
+        .find_map(|signature| {
+            let start = packets
+                .windows(signature.len())
+                .position(|window| window == *signature)?;
+            parse_vorbis_comment(&packets[start + signature.len()..], key)
+        })
+}
+
+/// Returns the value of the tag from ID3v2 tags of MP3 files or Vorbis comments of FLAC and OGG files
+fn parse_audio_tag(data: &[u8], tag: &AudioTag) -> Option<String> {
+    let value = if data.starts_with(b"ID3") {
+        parse_id3v2(data, tag.id3_frames)
+    } else if data.starts_with(b"fLaC") {
+        parse_flac(data, tag.vorbis_key)
+    } else if data.starts_with(b"OggS") {
+        parse_ogg(data, tag.vorbis_key)
+    } else {
+        None
+    }?;
+    tag.normalized(&value)
+}
+
+/// Reads the value of the tag of the MP3, FLAC or OGG file. Numbers are padded with zeros to the `width`
+///
+/// Returns None if the file can not be read or it has no such tag
+pub fn read_audio_tag(path: &str, tag: &AudioTag, width: usize) -> Option<String> {
+    let mut file = File::open(path).ok()?;
+    let mut data = vec![0; 10];
+    file.read_exact(&mut data).ok()?;
+    let limit = match data.starts_with(b"ID3") {
+        true => syncsafe(&data[6..10]) as u64,
+        false => VORBIS_HEADER_LIMIT,
+    };
+    file.take(limit).read_to_end(&mut data).ok()?;
+    let value = parse_audio_tag(&data, tag)?;
+    match tag.is_number() {
+        true => Some(format!("{value:0>width$}")),
+        false => Some(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id3v2(version: u8, frames: &[(&str, &[u8])]) -> Vec<u8> {
+        let size_bytes = |size: usize| match version {
+            3 => (size as u32).to_be_bytes(),
+            _ => [21, 14, 7, 0].map(|shift| ((size >> shift) & 0x7F) as u8),
+        };
+        let mut body = vec![];
+        for (id, text) in frames {
+            body.extend(id.as_bytes());
+            body.extend(size_bytes(text.len()));
+            body.extend([0, 0]);
+            body.extend(*text);
+        }
+        body.extend([0; 16]);
+        let size = [21, 14, 7, 0].map(|shift| ((body.len() >> shift) & 0x7F) as u8);
+        [b"ID3".as_slice(), &[version, 0, 0], &size, &body].concat()
+    }
+
+    fn vorbis_comment(comments: &[&str]) -> Vec<u8> {
+        let mut data = vec![];
+        data.extend(6u32.to_le_bytes());
+        data.extend(b"vendor");
+        data.extend((comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            data.extend((comment.len() as u32).to_le_bytes());
+            data.extend(comment.as_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn id3v2_test() {
+        let artist = AudioTag::from_name("artist").unwrap();
+        let track = AudioTag::from_name("track").unwrap();
+        let year = AudioTag::from_name("year").unwrap();
+        let utf16 = [
+            &[1, 0xFF, 0xFE][..],
+            &"Ölé"
+                .encode_utf16()
+                .flat_map(u16::to_le_bytes)
+                .collect::<Vec<u8>>(),
+        ]
+        .concat();
+        for version in [3, 4] {
+            let data = id3v2(
+                version,
+                &[
+                    ("TIT2", b"\x03Title"),
+                    ("TPE1", &utf16),
+                    ("TRCK", b"\x003/12"),
+                    ("TDRC", b"\x032023-05-07"),
+                ],
+            );
+            assert_eq!(parse_audio_tag(&data, artist), Some("Ölé".to_string()));
+            assert_eq!(parse_audio_tag(&data, track), Some("3".to_string()));
+            assert_eq!(parse_audio_tag(&data, year), Some("2023".to_string()));
+            assert_eq!(
+                parse_audio_tag(&data, AudioTag::from_name("album").unwrap()),
+                None
+            );
+        }
+        let data = id3v2(
+            3,
+            &[
+                ("TYER", b"\x001999"),
+                ("TALB", b"\x02\x00A\x00B\x00\x00\x00C"),
+            ],
+        );
+        assert_eq!(parse_audio_tag(&data, year), Some("1999".to_string()));
+        let album = AudioTag::from_name("album").unwrap();
+        assert_eq!(parse_audio_tag(&data, album), Some("AB".to_string()));
+        assert_eq!(parse_audio_tag(&data[..20], album), None);
+    }
+
+    #[test]
+    fn flac_test() {
+        let comment = vorbis_comment(&["artist=Band", "TRACKNUMBER=07"]);
+        let mut data = b"fLaC".to_vec();
+        data.extend([0x00, 0x00, 0x00, 0x02, 0xAA, 0xBB]);
+        data.push(0x84);
+        data.extend(&(comment.len() as u32).to_be_bytes()[1..]);
+        data.extend(&comment);
+        let artist = AudioTag::from_name("artist").unwrap();
+        assert_eq!(parse_audio_tag(&data, artist), Some("Band".to_string()));
+        let track = AudioTag::from_name("track").unwrap();
+        assert_eq!(parse_audio_tag(&data, track), Some("07".to_string()));
+        let title = AudioTag::from_name("title").unwrap();
+        assert_eq!(parse_audio_tag(&data, title), None);
+    }
+
+    #[test]
+    fn ogg_test() {
+        let page = |body: &[u8]| {
+            let mut page = b"OggS".to_vec();
+            page.extend([0; 22]);
+            let mut segments: Vec<u8> = vec![255; body.len() / 255];
+            segments.push((body.len() % 255) as u8);
+            page.push(segments.len() as u8);
+            page.extend(segments);
+            page.extend(body);
+            page
+        };
+        let comment = [b"\x03vorbis".as_slice(), &vorbis_comment(&["TITLE=Song"])].concat();
+        let data = [page(b"\x01vorbis identification"), page(&comment)].concat();
+        let title = AudioTag::from_name("title").unwrap();
+        assert_eq!(parse_audio_tag(&data, title), Some("Song".to_string()));
+
+        let comment = [b"OpusTags".as_slice(), &vorbis_comment(&["title=Opus"])].concat();
+        let data = [page(b"OpusHead"), page(&comment)].concat();
+        assert_eq!(parse_audio_tag(&data, title), Some("Opus".to_string()));
+    }
+}
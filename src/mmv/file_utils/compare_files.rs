@@ -0,0 +1,27 @@
+use std::fs::File;
+use std::io;
+use std::io::Read;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Returns true if both files have the same content. Sizes are compared first, so contents are read only
+/// if sizes are equal
+pub fn is_identical(first: &str, second: &str) -> io::Result<bool> {
+    let (mut first, mut second) = (File::open(first)?, File::open(second)?);
+    if first.metadata()?.len() != second.metadata()?.len() {
+        return Ok(false);
+    }
+
+    let mut first_chunk = vec![0; CHUNK_SIZE];
+    let mut second_chunk = vec![0; CHUNK_SIZE];
+    loop {
+        let read = first.read(&mut first_chunk)?;
+        if read == 0 {
+            return Ok(second.read(&mut second_chunk)? == 0);
+        }
+        second.read_exact(&mut second_chunk[..read])?;
+        if first_chunk[..read] != second_chunk[..read] {
+            return Ok(false);
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use crate::mmv::file_utils::NumberFormat;
+
+/// Sequence number of the matched file inserted by `#n` or `#{n:start=1,step=1,width=4}`
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Counter {
+    start: i64,
+    step: i64,
+    /// Minimal number of digits, the number is padded with zeros
+    width: usize,
+}
+
+impl Default for Counter {
+    fn default() -> Self {
+        Self {
+            start: 1,
+            step: 1,
+            width: 0,
+        }
+    }
+}
+
+impl Counter {
+    /// Parses comma separated parameters `start`, `step` and `width`, every parameter is optional
+    pub fn parse(params: &str) -> Result<Self, &'static str> {
+        let mut counter = Self::default();
+        for param in params.split(',').filter(|param| !param.trim().is_empty()) {
+            let (key, value) = param
+                .split_once('=')
+                .ok_or("Counter parameters must look like start=1,step=1,width=4")?;
+            let value = value.trim();
+            match key.trim() {
+                "start" => {
+                    counter.start = value
+                        .parse()
+                        .map_err(|_| "Counter start must be an integer")?
+                }
+                "step" => {
+                    counter.step = value
+                        .parse()
+                        .map_err(|_| "Counter step must be an integer")?
+                }
+                "width" => {
+                    counter.width = value
+                        .parse()
+                        .ok()
+                        .filter(|width| *width <= NumberFormat::MAX_WIDTH)
+                        .ok_or("Counter width must be an integer from 0 to 255")?
+                }
+                _ => return Err("Counter parameters are start, step and width"),
+            }
+        }
+        Ok(counter)
+    }
+
+    /// Counter value of the file with the `index` in the order of matched files.
+    /// Returns None if the value overflows
+    pub fn format(&self, index: usize) -> Option<String> {
+        let value = i64::try_from(index)
+            .ok()
+            .and_then(|index| self.step.checked_mul(index))
+            .and_then(|offset| self.start.checked_add(offset))?;
+        Some(format!("{value:0width$}", width = self.width))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_test() {
+        assert_eq!(Counter::parse(""), Ok(Counter::default()));
+        assert_eq!(
+            Counter::parse("width=4, start=0"),
+            Ok(Counter {
+                start: 0,
+                step: 1,
+                width: 4
+            })
+        );
+        assert_eq!(
+            Counter::parse("step=-10"),
+            Ok(Counter {
+                start: 1,
+                step: -10,
+                width: 0
+            })
+        );
+        assert!(Counter::parse("width=-1").is_err());
+        assert!(Counter::parse("width=256").is_err());
+        assert!(Counter::parse("width=99999999999").is_err());
+        assert!(Counter::parse("start").is_err());
+        assert!(Counter::parse("size=4").is_err());
+    }
+
+    #[test]
+    fn format_test() {
+        assert_eq!(Counter::default().format(0), Some("1".to_string()));
+        assert_eq!(Counter::default().format(9), Some("10".to_string()));
+        assert_eq!(
+            Counter::parse("width=4").unwrap().format(0),
+            Some("0001".to_string())
+        );
+        assert_eq!(
+            Counter::parse("start=10,step=5,width=3").unwrap().format(2),
+            Some("020".to_string())
+        );
+        assert_eq!(
+            Counter::parse("start=1,step=-1,width=2").unwrap().format(3),
+            Some("-2".to_string())
+        );
+        let start = format!("start={}", i64::MAX);
+        assert_eq!(
+            Counter::parse(&start).unwrap().format(0),
+            Some(i64::MAX.to_string())
+        );
+        assert_eq!(Counter::parse(&start).unwrap().format(1), None);
+        assert_eq!(Counter::parse("step=-2").unwrap().format(usize::MAX), None);
+    }
+}
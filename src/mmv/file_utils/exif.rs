@@ -0,0 +1,257 @@
+use chrono::NaiveDateTime;
+use std::fs::File;
+use std::io::Read;
+
+/// EXIF headers are located at the beginning of files, the rest of the file is not read
+const EXIF_HEADER_LIMIT: u64 = 256 * 1024;
+
+const EXIF_IFD_POINTER: u16 = 0x8769;
+
+/// EXIF tag inserted by `#{exif:Tag}` or `#{exif:Tag:format}` for date tags
+#[derive(Debug, Eq, PartialEq)]
+pub struct ExifTag {
+    pub name: &'static str,
+    id: u16,
+    /// The tag is located in the EXIF sub-IFD, otherwise in IFD0
+    in_exif_ifd: bool,
+    /// The value is a date like `2023:05:07 13:04:05`
+    pub is_date: bool,
+}
+
+const fn tag(name: &'static str, id: u16, in_exif_ifd: bool, is_date: bool) -> ExifTag {
+    ExifTag {
+        name,
+        id,
+        in_exif_ifd,
+        is_date,
+    }
+}
+
+/// Supported tags with text and integer values
+const EXIF_TAGS: &[ExifTag] = &[
+    tag("ImageDescription", 0x010E, false, false),
+    tag("Make", 0x010F, false, false),
+    tag("Model", 0x0110, false, false),
+    tag("Orientation", 0x0112, false, false),
+    tag("Software", 0x0131, false, false),
+    tag("DateTime", 0x0132, false, true),
+    tag("Artist", 0x013B, false, false),
+    tag("Copyright", 0x8298, false, false),
+    tag("ISOSpeedRatings", 0x8827, true, false),
+    tag("DateTimeOriginal", 0x9003, true, true),
+    tag("DateTimeDigitized", 0x9004, true, true),
+    tag("PixelXDimension", 0xA002, true, false),
+    tag("PixelYDimension", 0xA003, true, false),
+    tag("BodySerialNumber", 0xA431, true, false),
+    tag("LensMake", 0xA433, true, false),
+    tag("LensModel", 0xA434, true, false),
+];
+
+impl ExifTag {
+    pub fn from_name(name: &str) -> Option<&'static ExifTag> {
+        EXIF_TAGS.iter().find(|tag| tag.name == name)
+    }
+}
+
+/// Returns the TIFF structure of a TIFF file or of the EXIF segment of a JPEG file
+fn tiff_data(data: &[u8]) -> Option<&[u8]> {
+    if data.starts_with(b"II*\0") || data.starts_with(b"MM\0*") {
+        return Some(data);
+    }
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut position = 2;
+    loop {
+        if *data.get(position)? != 0xFF {
+            return None;
+        }
+        let marker = *data.get(position + 1)?;
+        match marker {
+            // Fill byte
+            0xFF => position += 1,
+            // Markers without segments
+            0x01 | 0xD0..=0xD7 => position += 2,
+            // Image data starts, EXIF must precede it
+            0xD9 | 0xDA => return None,
+            _ => {
+                let length =
+                    u16::from_be_bytes([*data.get(position + 2)?, *data.get(position + 3)?]);
+                let segment = data.get(position + 4..position + 2 + length as usize)?;
+                if marker == 0xE1 && segment.starts_with(b"Exif\0\0") {
+                    return Some(&segment[6..]);
+                }
+                position += 2 + length as usize;
+            }
+        }
+    }
+}
+
+struct Tiff<'a> {
+    data: &'a [u8],
+    is_big_endian: bool,
+}
+
+impl Tiff<'_> {
+    fn u16(&self, offset: usize) -> Option<u16> {
+        let bytes = [*self.data.get(offset)?, *self.data.get(offset + 1)?];
+        Some(match self.is_big_endian {
+            true => u16::from_be_bytes(bytes),
+            false => u16::from_le_bytes(bytes),
+        })
+    }
+
+    fn u32(&self, offset: usize) -> Option<u32> {
+        let bytes: [u8; 4] = self.data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(match self.is_big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        })
+    }
+
+    /// Offset of the 12 bytes entry of the tag in the IFD
+    fn find_entry(&self, ifd_offset: usize, id: u16) -> Option<usize> {
+        let count = self.u16(ifd_offset)? as usize;
+        (0..count)
+            .map(|idx| ifd_offset + 2 + idx * 12)
+            .find(|&entry| self.u16(entry) == Some(id))
+    }
+
+    /// Text of ASCII values, the first number of SHORT and LONG values. Other types are not supported
+    fn value(&self, entry: usize) -> Option<String> {
+        let value_type = self.u16(entry + 2)?;
+        let count = self.u32(entry + 4)? as usize;
+        match value_type {
+            // ASCII
+            2 => {
+                let offset = match count <= 4 {
+                    true => entry + 8,
+                    false => self.u32(entry + 8)? as usize,
+                };
+                let bytes = self.data.get(offset..offset + count)?;
+                let text = bytes.split(|&byte| byte == 0).next()?;
+                Some(String::from_utf8_lossy(text).trim().to_string())
+            }
+            // SHORT
+            3 => Some(self.u16(entry + 8)?.to_string()),
+            // LONG
+            4 => Some(self.u32(entry + 8)?.to_string()),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the value of the tag from the TIFF structure of `data`, e.g. the content of a JPEG or TIFF file
+fn parse_exif_tag(data: &[u8], tag: &ExifTag) -> Option<String> {
+    let data = tiff_data(data)?;
+    let tiff = Tiff {
+        data,
+        is_big_endian: data.starts_with(b"MM"),
+    };
+    let mut ifd_offset = tiff.u32(4)? as usize;
+    if tag.in_exif_ifd {
+        let pointer = tiff.find_entry(ifd_offset, EXIF_IFD_POINTER)?;
+        ifd_offset = tiff.u32(pointer + 8)? as usize;
+    }
+    let value = tiff.value(tiff.find_entry(ifd_offset, tag.id)?)?;
+    (!value.is_empty()).then_some(value)
+}
+
+/// Reads the value of the tag from the EXIF header of the JPEG or TIFF file. Dates are formatted with the
+/// strftime `date_format`
+///
+/// Returns None if the file can not be read, it has no such tag or the date is incorrect
+pub fn read_exif_tag(path: &str, tag: &ExifTag, date_format: &str) -> Option<String> {
+    let mut data = vec![];
+    File::open(path)
+        .ok()?
+        .take(EXIF_HEADER_LIMIT)
+        .read_to_end(&mut data)
+        .ok()?;
+    let value = parse_exif_tag(&data, tag)?;
+    match tag.is_date {
+        true => NaiveDateTime::parse_from_str(&value, "%Y:%m:%d %H:%M:%S")
+            .ok()
+            .map(|date| date.format(date_format).to_string()),
+        false => Some(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// TIFF structure with `Model` in IFD0 and `DateTimeOriginal` in the EXIF sub-IFD
+    fn tiff(is_big_endian: bool) -> Vec<u8> {
+        let u16_bytes = |value: u16| match is_big_endian {
+            true => value.to_be_bytes(),
+            false => value.to_le_bytes(),
+        };
+        let u32_bytes = |value: u32| match is_big_endian {
+            true => value.to_be_bytes(),
+            false => value.to_le_bytes(),
+        };
+        let mut data = match is_big_endian {
+            true => b"MM\0*".to_vec(),
+            false => b"II*\0".to_vec(),
+        };
+        data.extend(u32_bytes(8));
+        // IFD0 at 8: Model at 38, EXIF IFD at 44
+        data.extend(u16_bytes(2));
+        data.extend([u16_bytes(0x0110), u16_bytes(2)].concat());
+        data.extend([u32_bytes(6), u32_bytes(38)].concat());
+        data.extend([u16_bytes(0x8769), u16_bytes(4)].concat());
+        data.extend([u32_bytes(1), u32_bytes(44)].concat());
+        data.extend(u32_bytes(0));
+        data.extend(b"X100\0\0");
+        // EXIF IFD at 44: DateTimeOriginal at 62
+        data.extend(u16_bytes(1));
+        data.extend([u16_bytes(0x9003), u16_bytes(2)].concat());
+        data.extend([u32_bytes(20), u32_bytes(62)].concat());
+        data.extend(u32_bytes(0));
+        data.extend(b"2023:05:07 13:04:05\0");
+        data
+    }
+
+    fn jpeg(tiff: &[u8]) -> Vec<u8> {
+        let mut data = vec![0xFF, 0xD8];
+        data.extend([0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00]);
+        data.extend([0xFF, 0xE1]);
+        data.extend((tiff.len() as u16 + 8).to_be_bytes());
+        data.extend(b"Exif\0\0");
+        data.extend(tiff);
+        data.extend([0xFF, 0xDA, 0x00, 0x02, 0xFF, 0xD9]);
+        data
+    }
+
+    #[test]
+    fn parse_exif_tag_test() {
+        let model = ExifTag::from_name("Model").unwrap();
+        let date = ExifTag::from_name("DateTimeOriginal").unwrap();
+        let make = ExifTag::from_name("Make").unwrap();
+        for data in [
+            tiff(false),
+            tiff(true),
+            jpeg(&tiff(false)),
+            jpeg(&tiff(true)),
+        ] {
+            assert_eq!(parse_exif_tag(&data, model), Some("X100".to_string()));
+            assert_eq!(
+                parse_exif_tag(&data, date),
+                Some("2023:05:07 13:04:05".to_string())
+            );
+            assert_eq!(parse_exif_tag(&data, make), None);
+        }
+        assert_eq!(parse_exif_tag(b"", model), None);
+        assert_eq!(parse_exif_tag(b"not an image", model), None);
+        assert_eq!(parse_exif_tag(&tiff(false)[..40], model), None);
+        assert_eq!(parse_exif_tag(&jpeg(b"")[..8], model), None);
+    }
+
+    #[test]
+    fn from_name_test() {
+        assert!(ExifTag::from_name("DateTimeOriginal").unwrap().is_date);
+        assert!(!ExifTag::from_name("Model").unwrap().is_date);
+        assert_eq!(ExifTag::from_name("model"), None);
+    }
+}
@@ -2,51 +2,111 @@ use regex::{escape, Captures, Regex};
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum TemplateError {
-    AsteriskInDirectory,
     DoubleAsterisk,
 }
 
+/// Input file path template.
+///
+/// Asterisks may appear in any part of the path, each of them captures a part of a single path component.
+/// Double asterisk `**` used as a whole directory component captures zero or more directories (including `/`
+/// between them). Captures are numbered from left to right across the whole path.
 pub struct Template {
     pattern: Regex,
+    search_root: String,
+    search_depth: Option<usize>,
 }
 
 impl Template {
     pub fn new(pattern: &str) -> Result<Template, TemplateError> {
-        if pattern.rfind('/').unwrap_or(0) > pattern.find('*').unwrap_or(pattern.len()) {
-            return Err(TemplateError::AsteriskInDirectory);
-        }
-        if pattern.contains("**") {
-            return Err(TemplateError::DoubleAsterisk);
+        let components: Vec<&str> = pattern.split('/').collect();
+        let last_component = components.len() - 1;
+
+        let mut regex_pattern = String::from("^");
+        for (idx, component) in components.iter().enumerate() {
+            if *component == "**" && idx != last_component {
+                regex_pattern.push_str("(?:(.+)/)?");
+                continue;
+            }
+            if component.contains("**") {
+                return Err(TemplateError::DoubleAsterisk);
+            }
+            regex_pattern.push_str(&Self::component_pattern(component));
+            if idx != last_component {
+                regex_pattern.push('/');
+            }
         }
+        regex_pattern.push('$');
 
-        let escaped_pattern = escape(&String::from(pattern));
-        let pattern = format!(
-            "^{}$",
-            Regex::new(r#"(^|[^\\])\\\*"#)
-                .unwrap()
-                .replace_all(&escaped_pattern, |caps: &Captures| format!(
-                    "{}([^/]*)",
-                    &caps[1]
-                ),)
-                .replace(r#"\\\*"#, r#"\*"#)
-        );
+        let (search_root, search_depth) = Self::split_literal_prefix(pattern);
 
         Ok(Self {
-            pattern: Regex::new(&pattern).unwrap(),
+            pattern: Regex::new(&regex_pattern).unwrap(),
+            search_root,
+            search_depth,
         })
     }
 
+    fn component_pattern(component: &str) -> String {
+        let escaped_component = escape(component);
+        Regex::new(r#"(^|[^\\])\\\*"#)
+            .unwrap()
+            .replace_all(&escaped_component, |caps: &Captures| {
+                format!("{}([^/]*)", &caps[1])
+            })
+            .replace(r#"\\\*"#, r#"\*"#)
+    }
+
+    /// Splits the pattern on the deepest directory that contains no captures.
+    ///
+    /// Returns this directory (with unescaped asterisks) and the number of path components after it,
+    /// or None if the number is not limited because of `**`
+    fn split_literal_prefix(pattern: &str) -> (String, Option<usize>) {
+        let first_asterisk = Regex::new(r#"(^|[^\\])\*"#)
+            .unwrap()
+            .find(pattern)
+            .map_or(pattern.len(), |found| found.end() - 1);
+        let (search_root, rest) = match pattern[..first_asterisk].rfind('/') {
+            None => ("", pattern),
+            Some(0) => ("/", &pattern[1..]),
+            Some(slash) => (&pattern[..slash], &pattern[slash + 1..]),
+        };
+
+        let search_depth = match rest.split('/').any(|component| component == "**") {
+            true => None,
+            false => Some(rest.split('/').count()),
+        };
+
+        (search_root.replace(r#"\*"#, "*"), search_depth)
+    }
+
+    /// The deepest directory of the template without captures. All matching paths are located inside it
+    ///
+    /// Empty string means the current directory
+    pub fn search_root(&self) -> &str {
+        &self.search_root
+    }
+
+    /// Number of path components between [search_root](Template::search_root) and matching paths
+    ///
+    /// None means that matching paths can be located at any depth
+    pub fn search_depth(&self) -> Option<usize> {
+        self.search_depth
+    }
+
+    /// Number of captures in the template
+    pub fn captures_count(&self) -> usize {
+        self.pattern.captures_len() - 1
+    }
+
+    /// Returns captured parts of the string. Not matched `**` capture is returned as empty string
     pub fn captures<'a>(&self, string: &'a str) -> Option<Vec<&'a str>> {
-        match self.pattern.captures(string) {
-            None => None,
-            Some(captures) => Some(
-                captures
-                    .iter()
-                    .skip(1)
-                    .map(|capture| string.get(capture.unwrap().range()).unwrap())
-                    .collect(),
-            ),
-        }
+        self.pattern.captures(string).map(|captures| {
+            captures
+                .iter()
+                .skip(1)
+                .map(|capture| capture.map_or("", |capture| capture.as_str()))
+                .collect()
+        })
     }
 }
 
@@ -65,11 +125,11 @@ mod tests {
     #[test]
     fn test_incorrect_template() {
         assert_eq!(
-            Template::new("/path/to/*/*.png").err().unwrap(),
-            TemplateError::AsteriskInDirectory
+            Template::new("/path/to/**.png").err().unwrap(),
+            TemplateError::DoubleAsterisk
         );
         assert_eq!(
-            Template::new("/path/to/**.png").err().unwrap(),
+            Template::new("/path/to/**").err().unwrap(),
             TemplateError::DoubleAsterisk
         );
     }
@@ -113,4 +173,75 @@ mod tests {
         );
         assert_eq!(template.captures("path/to/some_filename.jpg"), None);
     }
+
+    #[test]
+    fn test_directory_captures() {
+        let template = Template::new("photos/2023_*/raw/*.cr2").unwrap();
+        assert_eq!(
+            template.captures("photos/2023_01/raw/img.cr2"),
+            Some(vec!["01", "img"])
+        );
+        assert_eq!(template.captures("photos/2023_01/02/raw/img.cr2"), None);
+        assert_eq!(template.captures("photos/2023_01/img.cr2"), None);
+        assert_eq!(template.search_root(), "photos");
+        assert_eq!(template.search_depth(), Some(3));
+    }
+
+    #[test]
+    fn test_recursive_captures() {
+        let template = Template::new("src/**/*.jpeg").unwrap();
+        assert_eq!(template.captures_count(), 2);
+        assert_eq!(template.captures("src/a.jpeg"), Some(vec!["", "a"]));
+        assert_eq!(template.captures("src/dir/a.jpeg"), Some(vec!["dir", "a"]));
+        assert_eq!(
+            template.captures("src/dir/sub/a.jpeg"),
+            Some(vec!["dir/sub", "a"])
+        );
+        assert_eq!(template.captures("src/dir/a.jpg"), None);
+        assert_eq!(template.captures("other/dir/a.jpeg"), None);
+        assert_eq!(template.search_root(), "src");
+        assert_eq!(template.search_depth(), None);
+
+        let template = Template::new("**/raw/*_*.cr2").unwrap();
+        assert_eq!(template.captures("raw/a_b.cr2"), Some(vec!["", "a", "b"]));
+        assert_eq!(
+            template.captures("2023/01/raw/a_b.cr2"),
+            Some(vec!["2023/01", "a", "b"])
+        );
+        assert_eq!(template.captures("2023/01/a_b.cr2"), None);
+        assert_eq!(template.search_root(), "");
+    }
+
+    #[test]
+    fn test_search_root() {
+        let template = Template::new("/path/to/*.png").unwrap();
+        assert_eq!(
+            (template.search_root(), template.search_depth()),
+            ("/path/to", Some(1))
+        );
+
+        let template = Template::new("*/file.txt").unwrap();
+        assert_eq!(
+            (template.search_root(), template.search_depth()),
+            ("", Some(2))
+        );
+
+        let template = Template::new("/*/file.txt").unwrap();
+        assert_eq!(
+            (template.search_root(), template.search_depth()),
+            ("/", Some(2))
+        );
+
+        let template = Template::new("path/to/file.txt").unwrap();
+        assert_eq!(
+            (template.search_root(), template.search_depth()),
+            ("path/to", Some(1))
+        );
+
+        let template = Template::new(r#"dir\*/sub/*.txt"#).unwrap();
+        assert_eq!(
+            (template.search_root(), template.search_depth()),
+            ("dir*/sub", Some(1))
+        );
+    }
 }
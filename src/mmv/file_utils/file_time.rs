@@ -0,0 +1,114 @@
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local};
+use std::fmt::Write;
+use std::fs;
+use std::fs::Metadata;
+use std::time::SystemTime;
+
+/// Format of dates inserted without an explicit format, e.g. `#{mtime}`
+pub const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Timestamp of the source file inserted by `#{mtime:format}`, `#{btime:format}`, `#{ctime:format}` or
+/// `#{atime:format}`
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum FileTime {
+    Modified,
+    /// Creation (birth) time, not the inode change time. Modification time is used where the creation time is
+    /// not available
+    Created,
+    /// Inode change time on Unix, it is updated when the file is renamed or its permissions are changed.
+    /// Modification time is used on other platforms
+    Changed,
+    Accessed,
+}
+
+/// Inode change time of the file
+#[cfg(unix)]
+fn changed(metadata: &Metadata) -> Option<SystemTime> {
+    use std::os::unix::fs::MetadataExt;
+    use std::time::Duration;
+
+    let seconds = Duration::from_secs(metadata.ctime().unsigned_abs());
+    let time = match metadata.ctime() >= 0 {
+        true => SystemTime::UNIX_EPOCH.checked_add(seconds),
+        false => SystemTime::UNIX_EPOCH.checked_sub(seconds),
+    }?;
+    time.checked_add(Duration::from_nanos(metadata.ctime_nsec().try_into().ok()?))
+}
+
+/// Modification time, the inode change time is not available
+#[cfg(not(unix))]
+fn changed(metadata: &Metadata) -> Option<SystemTime> {
+    metadata.modified().ok()
+}
+
+impl FileTime {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "mtime" => Some(Self::Modified),
+            "btime" => Some(Self::Created),
+            "ctime" => Some(Self::Changed),
+            "atime" => Some(Self::Accessed),
+            _ => None,
+        }
+    }
+
+    /// Returns None if the metadata of the path can not be read
+    pub fn of(&self, path: &str) -> Option<SystemTime> {
+        let metadata = fs::metadata(path).ok()?;
+        match self {
+            Self::Modified => metadata.modified().ok(),
+            Self::Created => metadata.created().or_else(|_| metadata.modified()).ok(),
+            Self::Changed => changed(&metadata),
+            Self::Accessed => metadata.accessed().ok(),
+        }
+    }
+}
+
+/// Returns true if the strftime format has only known specifiers, e.g. `%Y/%m/%d`
+pub fn is_date_format_correct(format: &str) -> bool {
+    !StrftimeItems::new(format).any(|item| matches!(item, Item::Error))
+}
+
+/// Returns true if the format is [correct](is_date_format_correct) and has no time zone specifiers like `%z`,
+/// dates without a time zone, e.g. EXIF dates, can not be formatted with them
+pub fn is_naive_date_format_correct(format: &str) -> bool {
+    let date = DateTime::UNIX_EPOCH.naive_utc();
+    is_date_format_correct(format) && write!(String::new(), "{}", date.format(format)).is_ok()
+}
+
+/// Formats the time in the local time zone. The format must be [correct](is_date_format_correct)
+pub fn format_time(time: SystemTime, format: &str) -> String {
+    DateTime::<Local>::from(time).format(format).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn is_date_format_correct_test() {
+        assert!(is_date_format_correct(""));
+        assert!(is_date_format_correct("%Y/%m/%d"));
+        assert!(is_date_format_correct("%Y-%m-%d_%H%M%S"));
+        assert!(!is_date_format_correct("%Y-%"));
+        assert!(!is_date_format_correct("%Q"));
+    }
+
+    #[test]
+    fn is_naive_date_format_correct_test() {
+        assert!(is_naive_date_format_correct("%Y:%m:%d %H%M%S"));
+        assert!(!is_naive_date_format_correct("%Y%z"));
+        assert!(!is_naive_date_format_correct("%Z"));
+        assert!(!is_naive_date_format_correct("%+"));
+        assert!(!is_naive_date_format_correct("%Q"));
+    }
+
+    #[test]
+    fn format_time_test() {
+        let time = Local.with_ymd_and_hms(2023, 5, 7, 13, 4, 5).unwrap();
+        assert_eq!(format_time(time.into(), "%Y/%m/%d"), "2023/05/07");
+        assert_eq!(format_time(time.into(), "%H-%M-%S"), "13-04-05");
+    }
+}
@@ -0,0 +1,69 @@
+use std::fs;
+use std::io;
+
+use crate::mmv::MatchKind;
+
+/// Joins directory path and file name without normalization, so the result keeps the form of the template
+pub fn join_path(directory: &str, name: &str) -> String {
+    if directory.is_empty() {
+        name.to_string()
+    } else if directory.ends_with('/') {
+        format!("{directory}{name}")
+    } else {
+        format!("{directory}/{name}")
+    }
+}
+
+fn read_dir(directory: &str) -> io::Result<fs::ReadDir> {
+    fs::read_dir(if directory.is_empty() { "." } else { directory })
+}
+
+/// Returns all paths of `match_kind` located exactly `depth` path components below `root`, or at any depth
+/// if `depth` is None
+///
+/// Returns Err if `root` can not be read. Unreadable nested directories are ignored
+pub fn find_candidates(
+    root: &str,
+    depth: Option<usize>,
+    match_kind: MatchKind,
+) -> io::Result<Vec<String>> {
+    let mut candidates = vec![];
+    let mut level = vec![root.to_string()];
+    let mut root_entries = Some(read_dir(root)?);
+
+    let mut current_depth = 0;
+    while !level.is_empty() && depth.is_none_or(|depth| current_depth < depth) {
+        current_depth += 1;
+        let is_last = depth == Some(current_depth);
+
+        let mut next_level = vec![];
+        for directory in &level {
+            let entries = match root_entries.take() {
+                Some(entries) => entries,
+                None => match read_dir(directory) {
+                    Ok(entries) => entries,
+                    Err(_) => continue,
+                },
+            };
+            for entry in entries.filter_map(|entry| entry.ok()) {
+                let Ok(file_type) = entry.file_type() else {
+                    continue;
+                };
+                let path = join_path(directory, &entry.file_name().to_string_lossy());
+                let is_candidate = match file_type.is_dir() {
+                    true => match_kind.matches_directories(),
+                    false => file_type.is_file() && match_kind.matches_files(),
+                };
+                if is_candidate && (is_last || depth.is_none()) {
+                    candidates.push(path.clone());
+                }
+                if file_type.is_dir() && !is_last {
+                    next_level.push(path);
+                }
+            }
+        }
+        level = next_level;
+    }
+
+    Ok(candidates)
+}
@@ -1,5 +1,26 @@
+mod audio_tags;
+mod compare_files;
+mod counter;
+mod exif;
 mod file_template;
+mod file_time;
+mod find_candidates;
+mod move_file;
+mod number_format;
+mod relative_path;
 mod resolve_path_pattern;
+mod sanitize;
+mod text_case;
 
+pub use compare_files::is_identical;
+pub use counter::Counter;
 pub use file_template::{Template, TemplateError};
-pub use resolve_path_pattern::resolve_path_pattern;
+pub use file_time::FileTime;
+pub use find_candidates::{find_candidates, join_path};
+pub use move_file::move_file;
+pub use number_format::NumberFormat;
+pub use relative_path::relative_path;
+pub use resolve_path_pattern::{
+    check_path_pattern, placeholders, resolve_path_pattern, PatternError,
+};
+pub use text_case::CaseModifier;
@@ -0,0 +1,88 @@
+use std::fs;
+use std::fs::{File, FileTimes, Metadata};
+use std::io;
+use std::path::Path;
+
+/// Sets access/modification times of `metadata` to the path
+fn copy_times(metadata: &Metadata, to: &str) -> io::Result<()> {
+    let mut times = FileTimes::new();
+    if let Ok(accessed) = metadata.accessed() {
+        times = times.set_accessed(accessed);
+    }
+    if let Ok(modified) = metadata.modified() {
+        times = times.set_modified(modified);
+    }
+    match metadata.is_dir() {
+        true => File::open(to)?.set_times(times),
+        false => File::options().write(true).open(to)?.set_times(times),
+    }
+}
+
+/// Copies the file with its permissions and access/modification times.
+///
+/// Returns Err if the size of the copy is not equal to the size of the source
+fn copy_file(from: &str, to: &str) -> io::Result<()> {
+    let metadata = fs::metadata(from)?;
+    let copied = fs::copy(from, to)?;
+    if copied != metadata.len() || fs::metadata(to)?.len() != metadata.len() {
+        let _ = fs::remove_file(to);
+        return Err(io::Error::other(format!(
+            "copy of {from} to {to} is incomplete: {copied} of {} bytes",
+            metadata.len()
+        )));
+    }
+    copy_times(&metadata, to)
+}
+
+/// Copies the directory tree with permissions and access/modification times of its files and directories.
+/// Symbolic links are recreated, not followed
+fn copy_directory(from: &str, to: &str) -> io::Result<()> {
+    let metadata = fs::metadata(from)?;
+    fs::create_dir(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let from = entry.path().to_string_lossy().to_string();
+        let to = Path::new(to).join(entry.file_name());
+        let to = to.to_string_lossy();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            copy_directory(&from, &to)?;
+        } else if file_type.is_symlink() {
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(fs::read_link(&from)?, to.as_ref())?;
+            #[cfg(windows)]
+            copy_file(&from, &to)?;
+        } else {
+            copy_file(&from, &to)?;
+        }
+    }
+    fs::set_permissions(to, metadata.permissions())?;
+    copy_times(&metadata, to)
+}
+
+/// Copies the file or the directory tree, then removes the source.
+///
+/// The source is removed only if everything is copied, otherwise the partial copy is removed
+fn copy_and_remove(from: &str, to: &str) -> io::Result<()> {
+    if !fs::symlink_metadata(from)?.is_dir() {
+        copy_file(from, to)?;
+        return fs::remove_file(from);
+    }
+    if let Err(error) = copy_directory(from, to) {
+        let _ = fs::remove_dir_all(to);
+        return Err(error);
+    }
+    fs::remove_dir_all(from)
+}
+
+/// Moves the file or the directory with [fs::rename](fs::rename). If the destination is located on another
+/// filesystem, `before_copy` is called and the path is copied and removed instead
+pub fn move_file(from: &str, to: &str, before_copy: impl FnOnce()) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(error) if error.kind() == io::ErrorKind::CrossesDevices => {
+            before_copy();
+            copy_and_remove(from, to)
+        }
+        result => result,
+    }
+}
@@ -0,0 +1,96 @@
+/// Reformatting of a numeric capture, given after the flag like `#1:03d`
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum NumberFormat {
+    /// `03d`: the number is padded with zeros to the width
+    Padded(usize),
+    /// `+10` or `-10`: the offset is added to the number
+    Offset(i64),
+    /// `int`: leading zeros are stripped
+    Int,
+}
+
+impl NumberFormat {
+    /// Alternatives of the format names for the insertion flag regex
+    pub const NAMES: &'static str = r"\d+d|[+-]\d+|int";
+
+    /// Maximal width of zero-padded numbers, also used by the counter and audio tags
+    pub const MAX_WIDTH: usize = 255;
+
+    /// Returns None if the name is unknown or the width is greater than [MAX_WIDTH](Self::MAX_WIDTH)
+    pub fn from_name(name: &str) -> Option<Self> {
+        if name == "int" {
+            return Some(Self::Int);
+        }
+        if let Some(width) = name.strip_suffix('d') {
+            return width
+                .parse()
+                .ok()
+                .filter(|width| *width <= Self::MAX_WIDTH)
+                .map(Self::Padded);
+        }
+        match name.starts_with(['+', '-']) {
+            true => name.parse().ok().map(Self::Offset),
+            false => None,
+        }
+    }
+
+    /// Returns None if the text is not an integer
+    pub fn apply(&self, text: &str) -> Option<String> {
+        let digits = text.strip_prefix('-').unwrap_or(text);
+        if digits.is_empty() || !digits.chars().all(|ch| ch.is_ascii_digit()) {
+            return None;
+        }
+        let value: i64 = text.parse().ok()?;
+        Some(match self {
+            Self::Padded(width) => format!("{value:0width$}"),
+            Self::Offset(offset) => (value.checked_add(*offset)?).to_string(),
+            Self::Int => value.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_name_test() {
+        assert_eq!(
+            NumberFormat::from_name("03d"),
+            Some(NumberFormat::Padded(3))
+        );
+        assert_eq!(
+            NumberFormat::from_name("+10"),
+            Some(NumberFormat::Offset(10))
+        );
+        assert_eq!(
+            NumberFormat::from_name("-1"),
+            Some(NumberFormat::Offset(-1))
+        );
+        assert_eq!(NumberFormat::from_name("int"), Some(NumberFormat::Int));
+        assert_eq!(NumberFormat::from_name("d"), None);
+        assert_eq!(NumberFormat::from_name("10"), None);
+        assert_eq!(
+            NumberFormat::from_name("255d"),
+            Some(NumberFormat::Padded(255))
+        );
+        assert_eq!(NumberFormat::from_name("256d"), None);
+        assert_eq!(NumberFormat::from_name("99999999999d"), None);
+    }
+
+    #[test]
+    fn apply_test() {
+        let apply = |name: &str, text: &str| NumberFormat::from_name(name).unwrap().apply(text);
+        assert_eq!(apply("03d", "1"), Some("001".to_string()));
+        assert_eq!(apply("03d", "0012"), Some("012".to_string()));
+        assert_eq!(apply("2d", "123"), Some("123".to_string()));
+        assert_eq!(apply("+10", "5"), Some("15".to_string()));
+        assert_eq!(apply("-10", "5"), Some("-5".to_string()));
+        assert_eq!(apply("int", "007"), Some("7".to_string()));
+        assert_eq!(apply("int", "-007"), Some("-7".to_string()));
+        assert_eq!(apply("int", ""), None);
+        assert_eq!(apply("int", "1a"), None);
+        assert_eq!(apply("int", "+1"), None);
+        assert_eq!(apply("+1", "9223372036854775807"), None);
+    }
+}
@@ -0,0 +1,70 @@
+use std::io;
+use std::path::{absolute, Component, Path, PathBuf};
+
+/// Absolute path with `.` and `..` components resolved lexically, symbolic links are not followed.
+/// Empty path is the current directory
+fn normalized_path(path: &Path) -> io::Result<PathBuf> {
+    let path = match path.as_os_str().is_empty() {
+        true => Path::new("."),
+        false => path,
+    };
+    let mut normalized = PathBuf::new();
+    for component in absolute(path)?.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    Ok(normalized)
+}
+
+/// Path of `path` relative to the directory `base`, e.g. `../a/file` for `a/file` relative to `b`
+pub fn relative_path(path: &str, base: &str) -> io::Result<String> {
+    let path = normalized_path(Path::new(path))?;
+    let base = normalized_path(Path::new(base))?;
+
+    let common = path
+        .components()
+        .zip(base.components())
+        .take_while(|(path_component, base_component)| path_component == base_component)
+        .count();
+    let mut relative = PathBuf::new();
+    for _ in base.components().skip(common) {
+        relative.push(Component::ParentDir);
+    }
+    for component in path.components().skip(common) {
+        relative.push(component);
+    }
+    Ok(relative.to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_test() {
+        assert_eq!(relative_path("/a/b/file", "/a").unwrap(), "b/file");
+        assert_eq!(relative_path("/a/file", "/a/b/c").unwrap(), "../../file");
+        assert_eq!(relative_path("/a/b/file", "/a/c").unwrap(), "../b/file");
+        assert_eq!(relative_path("/file", "/").unwrap(), "file");
+    }
+
+    #[test]
+    fn dot_components_test() {
+        assert_eq!(
+            relative_path("/a/./b/../file", "/a/c/.").unwrap(),
+            "../file"
+        );
+        assert_eq!(relative_path("/a/file", "/a/b/..").unwrap(), "file");
+    }
+
+    #[test]
+    fn relative_arguments_test() {
+        assert_eq!(relative_path("a/file", "b").unwrap(), "../a/file");
+        assert_eq!(relative_path("file", "").unwrap(), "file");
+    }
+}
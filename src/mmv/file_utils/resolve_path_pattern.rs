@@ -1,52 +1,467 @@
 use regex::{Captures, Regex};
 
-pub fn resolve_path_pattern(path_pattern: &str, captures: Vec<&str>) -> String {
-    let placement_regex = Regex::new(r#"#(\d+)"#).unwrap();
-
-    placement_regex
-        .replace_all(path_pattern, |capture: &Captures| {
-            let index = capture.get(1).unwrap().as_str().parse::<usize>().unwrap();
-            if 1 <= index && index <= captures.len() {
-                captures[index - 1]
-            } else {
-                path_pattern.get(capture.get(0).unwrap().range()).unwrap()
+use crate::mmv::file_utils::audio_tags::{read_audio_tag, AudioTag};
+use crate::mmv::file_utils::exif::{read_exif_tag, ExifTag};
+use crate::mmv::file_utils::file_time::{
+    format_time, is_date_format_correct, is_naive_date_format_correct, DEFAULT_DATE_FORMAT,
+};
+use crate::mmv::file_utils::sanitize::sanitize_path_component;
+use crate::mmv::file_utils::{CaseModifier, Counter, FileTime, NumberFormat};
+use crate::mmv::MetadataFallback;
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum PatternError {
+    NotNumericCapture(/*capture=*/ String),
+    MissingMetadata(/*placeholder=*/ String),
+    /// The file lacks the tag and the [MetadataFallback](MetadataFallback) is `Skip`
+    MissingTag(/*placeholder=*/ String),
+}
+
+/// Regex of placeholders of the output template:
+/// - insertion flags `#<capture_index>` with optional modifiers, e.g. `#1:lower` or `#1:+10:03d`. Groups
+///   `index` and `modifiers`
+/// - the counter `#n`. Group `counter`, the letter or digit glued to it is captured by the group `glued`
+/// - named placeholders with optional parameters `#{name:params}`, e.g. `#{n:width=4}`,
+///   `#{mtime:%Y/%m/%d}`, `#{exif:DateTimeOriginal:%Y}` or `#{tag:track:02}`. Groups `name` and `params`
+fn placeholder_regex() -> Regex {
+    Regex::new(&format!(
+        r#"#(?:(?P<index>\d+)(?P<modifiers>(?::(?:{}|{}))*)|(?P<counter>n)(?P<glued>[A-Za-z0-9])?|\{{(?P<name>\w+)(?::(?P<params>[^}}]*))?\}})"#,
+        CaseModifier::NAMES,
+        NumberFormat::NAMES
+    ))
+    .unwrap()
+}
+
+/// Placeholders of the output template in order. `#n` followed by a letter or a digit is a literal text
+/// like `#name`, not the counter
+pub fn placeholders(path_pattern: &str) -> Vec<Captures<'_>> {
+    placeholder_regex()
+        .captures_iter(path_pattern)
+        .filter(|placeholder| placeholder.name("glued").is_none())
+        .collect()
+}
+
+/// Transformation of the inserted capture, modifiers of the flag are applied from left to right
+enum Modifier {
+    Case(CaseModifier),
+    Number(NumberFormat),
+}
+
+impl Modifier {
+    fn from_name(name: &str) -> Option<Self> {
+        CaseModifier::from_name(name)
+            .map(Self::Case)
+            .or_else(|| NumberFormat::from_name(name).map(Self::Number))
+    }
+
+    fn apply(&self, capture: &str) -> Result<String, PatternError> {
+        match self {
+            Self::Case(modifier) => Ok(modifier.apply(capture)),
+            Self::Number(format) => format
+                .apply(capture)
+                .ok_or_else(|| PatternError::NotNumericCapture(capture.to_string())),
+        }
+    }
+}
+
+enum Placeholder {
+    Capture {
+        index: usize,
+        modifiers: Vec<Modifier>,
+    },
+    Counter(Counter),
+    Time(FileTime, /*format=*/ String),
+    Exif(&'static ExifTag, /*date_format=*/ String),
+    Tag(&'static AudioTag, /*width=*/ usize),
+}
+
+fn parse_placeholder(placeholder: &Captures) -> Result<Placeholder, &'static str> {
+    if let Some(index) = placeholder.name("index") {
+        return Ok(Placeholder::Capture {
+            index: index.as_str().parse().unwrap(),
+            modifiers: placeholder
+                .name("modifiers")
+                .map_or("", |modifiers| modifiers.as_str())
+                .split(':')
+                .skip(1)
+                .map(|name| {
+                    Modifier::from_name(name)
+                        .ok_or("Width of the number format must be at most 255")
+                })
+                .collect::<Result<_, _>>()?,
+        });
+    }
+    if placeholder.name("counter").is_some() {
+        return Ok(Placeholder::Counter(Counter::default()));
+    }
+    let params = placeholder
+        .name("params")
+        .map_or("", |params| params.as_str());
+    let name = placeholder.name("name").unwrap().as_str();
+    if name == "n" {
+        return Ok(Placeholder::Counter(Counter::parse(params)?));
+    }
+    if let Some(file_time) = FileTime::from_name(name) {
+        let format = placeholder
+            .name("params")
+            .map_or(DEFAULT_DATE_FORMAT, |params| params.as_str());
+        return match is_date_format_correct(format) {
+            true => Ok(Placeholder::Time(file_time, format.to_string())),
+            false => Err("Incorrect strftime format of the date placeholder"),
+        };
+    }
+    if name == "exif" {
+        let (tag_name, format) = match params.split_once(':') {
+            Some((tag_name, format)) => (tag_name, Some(format)),
+            None => (params, None),
+        };
+        let tag = ExifTag::from_name(tag_name).ok_or("Unknown tag of the EXIF placeholder")?;
+        return match format {
+            Some(_) if !tag.is_date => Err("Date format is allowed only for EXIF date tags"),
+            Some(format) if !is_naive_date_format_correct(format) => {
+                Err("Incorrect strftime format of the EXIF placeholder, time zones are not allowed")
             }
+            _ => Ok(Placeholder::Exif(
+                tag,
+                format.unwrap_or(DEFAULT_DATE_FORMAT).to_string(),
+            )),
+        };
+    }
+    if name == "tag" {
+        let (tag_name, width) = match params.split_once(':') {
+            Some((tag_name, width)) => (tag_name, Some(width)),
+            None => (params, None),
+        };
+        let tag =
+            AudioTag::from_name(tag_name).ok_or("Unknown tag of the audio tag placeholder")?;
+        return match width {
+            Some(_) if !tag.is_number() => Err("Width is allowed only for track and disc tags"),
+            Some(width) => match width.chars().all(|ch| ch.is_ascii_digit()) {
+                true => width
+                    .parse()
+                    .ok()
+                    .filter(|width| *width <= NumberFormat::MAX_WIDTH)
+                    .map(|width| Placeholder::Tag(tag, width))
+                    .ok_or("Incorrect width of the audio tag placeholder, it must be at most 255"),
+                false => Err("Incorrect width of the audio tag placeholder"),
+            },
+            None => Ok(Placeholder::Tag(tag, 0)),
+        };
+    }
+    Err("Unknown placeholder, named placeholders are #{n}, #{mtime}, #{btime}, #{ctime}, #{atime}, #{exif} and #{tag}")
+}
+
+/// Returns Err with the description of the first incorrect placeholder of the pattern
+pub fn check_path_pattern(path_pattern: &str) -> Result<(), &'static str> {
+    placeholders(path_pattern)
+        .iter()
+        .try_for_each(|placeholder| parse_placeholder(placeholder).map(|_| ()))
+}
+
+/// Resolved part of the path between slashes of the pattern
+#[derive(Default)]
+struct ResolvedComponent {
+    text: String,
+    placeholders: usize,
+    has_literal: bool,
+}
+
+fn push_literal(components: &mut Vec<ResolvedComponent>, literal: &str) {
+    for (idx, part) in literal.split('/').enumerate() {
+        if idx > 0 {
+            components.push(ResolvedComponent::default());
+        }
+        let component = components.last_mut().unwrap();
+        component.text.push_str(part);
+        component.has_literal |= !part.is_empty();
+    }
+}
+
+/// Value inserted instead of the `placeholder` of the missing tag
+fn fallback_value(
+    fallback: &MetadataFallback,
+    source_path: &str,
+    placeholder: &str,
+    date_format: &str,
+) -> Result<String, PatternError> {
+    match fallback {
+        MetadataFallback::Skip => Err(PatternError::MissingTag(placeholder.to_string())),
+        MetadataFallback::Modified => FileTime::Modified
+            .of(source_path)
+            .map(|time| format_time(time, date_format))
+            .ok_or_else(|| PatternError::MissingMetadata("mtime".to_string())),
+        MetadataFallback::Default(value) => Ok(value.clone()),
+    }
+}
+
+/// Replaces placeholders of the pattern:
+/// - insertion flags `#<capture_index>` with corresponding captures. The capture is transformed by the
+///   modifiers of the flag: the case is changed by [CaseModifier](CaseModifier), numbers are reformatted
+///   by [NumberFormat](NumberFormat)
+/// - the counter `#n` or `#{n:start=1,step=1,width=4}` with the sequence number of the file with `index`
+///   in the order of matched files, see [Counter](Counter)
+/// - dates `#{mtime:format}`, `#{btime:format}`, `#{ctime:format}` and `#{atime:format}` with the modification,
+///   creation, inode change and access time of the `source_path` in the strftime format, `%Y-%m-%d` by default
+/// - EXIF tags `#{exif:Tag}` of JPEG and TIFF files, e.g. `#{exif:Model}`, dates are formatted like
+///   `#{exif:DateTimeOriginal:%Y-%m-%d}`, the format may add directories like `%Y/%m`. Text values are
+///   sanitized to be a single path component. If the file lacks the tag, the `fallback` is inserted
+/// - audio tags `#{tag:name}` of ID3v2 in MP3 files and Vorbis comments in FLAC and OGG files, e.g.
+///   `#{tag:artist}`, track and disc numbers are padded with zeros like `#{tag:track:02}`. Values are
+///   sanitized and missing tags are replaced like EXIF tags
+///
+/// Returns Err if a number format is applied to a capture that is not an integer, the counter overflows, the
+/// time of the source can not be read, or the tag is missing and the fallback is `Skip`.
+/// Incorrect placeholders are left as is.
+/// A directory of the path that consists only of a flag resolved to an empty capture is omitted, so
+/// `out/#1/#2` with captures `["", "file"]` is resolved to `out/file`
+pub fn resolve_path_pattern(
+    path_pattern: &str,
+    source_path: &str,
+    captures: Vec<&str>,
+    index: usize,
+    fallback: &MetadataFallback,
+) -> Result<String, PatternError> {
+    let mut components = vec![ResolvedComponent::default()];
+    let mut literal_start = 0;
+    for placeholder in placeholders(path_pattern) {
+        let whole = placeholder.get(0).unwrap();
+        push_literal(&mut components, &path_pattern[literal_start..whole.start()]);
+        literal_start = whole.end();
+
+        let resolved = match parse_placeholder(&placeholder) {
+            Ok(Placeholder::Capture {
+                index: capture_index,
+                modifiers,
+            }) if 1 <= capture_index && capture_index <= captures.len() => {
+                let mut capture = captures[capture_index - 1].to_string();
+                for modifier in &modifiers {
+                    capture = modifier.apply(&capture)?;
+                }
+                capture
+            }
+            Ok(Placeholder::Counter(counter)) => counter
+                .format(index)
+                .ok_or_else(|| PatternError::NotNumericCapture(whole.as_str().to_string()))?,
+            Ok(Placeholder::Time(file_time, format)) => {
+                let time = file_time.of(source_path).ok_or_else(|| {
+                    PatternError::MissingMetadata(placeholder["name"].to_string())
+                })?;
+                format_time(time, &format)
+            }
+            Ok(Placeholder::Exif(tag, date_format)) => {
+                match read_exif_tag(source_path, tag, &date_format) {
+                    // Dates are built by the format, so it may add directories like `%Y/%m`
+                    Some(value) if tag.is_date => value,
+                    Some(value) => sanitize_path_component(&value),
+                    None => fallback_value(fallback, source_path, whole.as_str(), &date_format)?,
+                }
+            }
+            Ok(Placeholder::Tag(tag, width)) => match read_audio_tag(source_path, tag, width) {
+                Some(value) => sanitize_path_component(&value),
+                None => fallback_value(fallback, source_path, whole.as_str(), DEFAULT_DATE_FORMAT)?,
+            },
+            _ => whole.as_str().to_string(),
+        };
+        let component = components.last_mut().unwrap();
+        component.text.push_str(&resolved);
+        component.placeholders += 1;
+    }
+    push_literal(&mut components, &path_pattern[literal_start..]);
+
+    let last_component = components.len() - 1;
+    Ok(components
+        .into_iter()
+        .enumerate()
+        .filter(|(idx, component)| {
+            let is_only_flag = component.placeholders == 1 && !component.has_literal;
+            !(component.text.is_empty() && is_only_flag && *idx != last_component)
         })
-        .into()
+        .map(|(_, component)| component.text)
+        .collect::<Vec<String>>()
+        .join("/"))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn resolved(path_pattern: &str, captures: Vec<&str>, index: usize) -> String {
+        resolve_path_pattern(path_pattern, "", captures, index, &MetadataFallback::Skip).unwrap()
+    }
+
     #[test]
     fn simple_test() {
-        assert_eq!(resolve_path_pattern("", vec![]), "");
-        assert_eq!(resolve_path_pattern("", vec!["capture"]), "");
-        assert_eq!(resolve_path_pattern("pattern", vec![]), "pattern");
-        assert_eq!(resolve_path_pattern("pattern", vec!["capture"]), "pattern");
-        assert_eq!(resolve_path_pattern("#1", vec!["capture"]), "capture");
-        assert_eq!(resolve_path_pattern("#1", vec![""]), "");
+        assert_eq!(resolved("", vec![], 0), "");
+        assert_eq!(resolved("", vec!["capture"], 0), "");
+        assert_eq!(resolved("pattern", vec![], 0), "pattern");
+        assert_eq!(resolved("pattern", vec!["capture"], 0), "pattern");
+        assert_eq!(resolved("#1", vec!["capture"], 0), "capture");
+        assert_eq!(resolved("#1", vec![""], 0), "");
     }
 
     #[test]
     fn multiple_usage_test() {
         assert_eq!(
-            resolve_path_pattern("double #1 #1", vec!["capture"]),
+            resolved("double #1 #1", vec!["capture"], 0),
             "double capture capture"
         );
         assert_eq!(
-            resolve_path_pattern("double #1 #2 #1", vec!["capture", "double"]),
+            resolved("double #1 #2 #1", vec!["capture", "double"], 0),
             "double capture double capture"
         );
-        assert_eq!(resolve_path_pattern("#1#1", vec!["test"]), "testtest");
-        assert_eq!(resolve_path_pattern("#1#1", vec![""]), "");
+        assert_eq!(resolved("#1#1", vec!["test"], 0), "testtest");
+        assert_eq!(resolved("#1#1", vec![""], 0), "");
     }
 
     #[test]
     fn wrong_patterns_test() {
-        assert_eq!(resolve_path_pattern("#0, #1, #2", vec!["ok"]), "#0, ok, #2");
-        assert_eq!(resolve_path_pattern("#0, #1", vec![]), "#0, #1");
-        assert_eq!(resolve_path_pattern("#0", vec![]), "#0");
+        assert_eq!(resolved("#0, #1, #2", vec!["ok"], 0), "#0, ok, #2");
+        assert_eq!(resolved("#0, #1", vec![], 0), "#0, #1");
+        assert_eq!(resolved("#0", vec![], 0), "#0");
+    }
+
+    #[test]
+    fn case_modifier_test() {
+        assert_eq!(
+            resolved("#1.#2:lower", vec!["IMG_1", "JPG"], 0),
+            "IMG_1.jpg"
+        );
+        assert_eq!(
+            resolved("#1:snake/#1:kebab", vec!["My Album"], 0),
+            "my_album/my-album"
+        );
+        assert_eq!(resolved("#1:upper:lower", vec!["a"], 0), "a");
+        assert_eq!(resolved("#1:unknown", vec!["a"], 0), "a:unknown");
+        assert_eq!(resolved("#2:upper", vec!["a"], 0), "#2:upper");
+        assert_eq!(resolved("out/#1:upper/#2", vec!["", "file"], 0), "out/file");
+    }
+
+    #[test]
+    fn number_format_test() {
+        assert_eq!(resolved("page_#1:03d", vec!["7"], 0), "page_007");
+        assert_eq!(resolved("page_#1:int", vec!["007"], 0), "page_7");
+        assert_eq!(
+            resolved("page_#1:+10:03d.#2", vec!["7", "png"], 0),
+            "page_017.png"
+        );
+        assert_eq!(resolved("#1:-1#1", vec!["1"], 0), "01");
+        assert_eq!(
+            resolve_path_pattern("page_#1:int", "", vec!["seven"], 0, &MetadataFallback::Skip),
+            Err(PatternError::NotNumericCapture("seven".to_string()))
+        );
+        assert_eq!(
+            resolve_path_pattern("#1:upper:03d", "", vec!["a1"], 0, &MetadataFallback::Skip),
+            Err(PatternError::NotNumericCapture("A1".to_string()))
+        );
+        assert!(check_path_pattern("#1:99999999999d").is_err());
+    }
+
+    #[test]
+    fn counter_test() {
+        assert_eq!(resolved("#n", vec![], 0), "1");
+        assert_eq!(
+            resolved("holiday_#{n:width=4}.#1", vec!["jpg"], 9),
+            "holiday_0010.jpg"
+        );
+        assert_eq!(resolved("#{n:start=0,step=10}/#{n}", vec![], 2), "20/3");
+        assert_eq!(resolved("#{x}#{n:size=1}", vec![], 0), "#{x}#{n:size=1}");
+        assert_eq!(resolved("out/#name.txt", vec![], 0), "out/#name.txt");
+        assert_eq!(resolved("#1_#notes_#n2", vec!["a"], 0), "a_#notes_#n2");
+        assert_eq!(resolved("#n_#1.#n", vec!["a"], 1), "2_a.2");
+        assert_eq!(check_path_pattern("#1_#n_#{n:start=5}"), Ok(()));
+        assert!(check_path_pattern("#{n:start=x}").is_err());
+        assert!(check_path_pattern("#{size}").is_err());
+        assert!(check_path_pattern("#{n:width=99999999999}").is_err());
+        let pattern = format!("#{{n:start={}}}", i64::MAX);
+        assert_eq!(
+            resolve_path_pattern(&pattern, "", vec![], 1, &MetadataFallback::Skip),
+            Err(PatternError::NotNumericCapture(pattern.clone()))
+        );
+    }
+
+    #[test]
+    fn date_test() {
+        assert_eq!(
+            check_path_pattern("#{mtime}/#{btime:%Y/%m}/#{atime:%H%M}"),
+            Ok(())
+        );
+        assert!(check_path_pattern("#{mtime:%Q}").is_err());
+        assert_eq!(check_path_pattern("#{ctime:%Y}"), Ok(()));
+        assert_eq!(
+            resolve_path_pattern(
+                "#{mtime:%Y}/#1",
+                "not/existing",
+                vec!["a"],
+                0,
+                &MetadataFallback::Skip
+            ),
+            Err(PatternError::MissingMetadata("mtime".to_string()))
+        );
+    }
+
+    #[test]
+    fn exif_test() {
+        assert_eq!(
+            check_path_pattern("#{exif:Model}/#{exif:DateTimeOriginal:%Y}"),
+            Ok(())
+        );
+        assert!(check_path_pattern("#{exif}").is_err());
+        assert!(check_path_pattern("#{exif:Unknown}").is_err());
+        assert!(check_path_pattern("#{exif:Model:%Y}").is_err());
+        assert!(check_path_pattern("#{exif:DateTime:%Q}").is_err());
+        assert!(check_path_pattern("#{exif:DateTime:%Y%z}").is_err());
+        assert!(check_path_pattern("#{exif:DateTimeOriginal:%Z}").is_err());
+
+        let resolve = |fallback: MetadataFallback| {
+            resolve_path_pattern("#{exif:Model}/#1", "not/existing", vec!["a"], 0, &fallback)
+        };
+        assert_eq!(
+            resolve(MetadataFallback::Skip),
+            Err(PatternError::MissingTag("#{exif:Model}".to_string()))
+        );
+        assert_eq!(
+            resolve(MetadataFallback::Default("unknown".to_string())),
+            Ok("unknown/a".to_string())
+        );
+        assert_eq!(
+            resolve(MetadataFallback::Modified),
+            Err(PatternError::MissingMetadata("mtime".to_string()))
+        );
+    }
+
+    #[test]
+    fn audio_tag_test() {
+        assert_eq!(
+            check_path_pattern("#{tag:artist}/#{tag:album}/#{tag:track:02} - #{tag:title}"),
+            Ok(())
+        );
+        assert!(check_path_pattern("#{tag}").is_err());
+        assert!(check_path_pattern("#{tag:Artist}").is_err());
+        assert!(check_path_pattern("#{tag:title:02}").is_err());
+        assert!(check_path_pattern("#{tag:track:x}").is_err());
+        assert!(check_path_pattern("#{tag:disc:-2}").is_err());
+        assert!(check_path_pattern("#{tag:disc:99999999999}").is_err());
+
+        let resolve = |fallback: MetadataFallback| {
+            resolve_path_pattern("#{tag:artist}/#1", "not/existing", vec!["a"], 0, &fallback)
+        };
+        assert_eq!(
+            resolve(MetadataFallback::Skip),
+            Err(PatternError::MissingTag("#{tag:artist}".to_string()))
+        );
+        assert_eq!(
+            resolve(MetadataFallback::Default("unknown".to_string())),
+            Ok("unknown/a".to_string())
+        );
+    }
+
+    #[test]
+    fn empty_directory_test() {
+        assert_eq!(resolved("out/#1/#2", vec!["", "file"], 0), "out/file");
+        assert_eq!(resolved("#1/#2", vec!["", "file"], 0), "file");
+        assert_eq!(resolved("#1/#2", vec!["a/b", "file"], 0), "a/b/file");
+        assert_eq!(resolved("/#1/#2", vec!["", "file"], 0), "/file");
+        assert_eq!(resolved("x#1/#2", vec!["", "file"], 0), "x/file");
+        assert_eq!(resolved("out/#1", vec![""], 0), "out/");
     }
 }
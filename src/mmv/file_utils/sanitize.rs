@@ -0,0 +1,37 @@
+/// Characters that are not allowed in file names on some platforms
+const UNSAFE_CHARS: &[char] = &['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+/// Makes a metadata value safe to be a single path component: unsafe and control characters are replaced
+/// with `_`, surrounding whitespaces and trailing dots are removed. Empty, `.` and `..` values become `_`
+pub fn sanitize_path_component(value: &str) -> String {
+    let sanitized: String = value
+        .trim()
+        .chars()
+        .map(|ch| match UNSAFE_CHARS.contains(&ch) || ch.is_control() {
+            true => '_',
+            false => ch,
+        })
+        .collect();
+    let sanitized = sanitized.trim_end_matches('.').trim_end();
+    match sanitized.is_empty() {
+        true => "_".to_string(),
+        false => sanitized.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_path_component_test() {
+        assert_eq!(sanitize_path_component("Canon EOS 5D"), "Canon EOS 5D");
+        assert_eq!(sanitize_path_component("AC/DC"), "AC_DC");
+        assert_eq!(sanitize_path_component("What? <Live>"), "What_ _Live_");
+        assert_eq!(sanitize_path_component("  a\tb\n "), "a_b");
+        assert_eq!(sanitize_path_component("Vol. 2..."), "Vol. 2");
+        assert_eq!(sanitize_path_component(""), "_");
+        assert_eq!(sanitize_path_component(".."), "_");
+        assert_eq!(sanitize_path_component(" "), "_");
+    }
+}
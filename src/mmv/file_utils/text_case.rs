@@ -0,0 +1,146 @@
+/// Case transformation of the inserted capture, given after the flag like `#1:lower`
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum CaseModifier {
+    /// `MY FILE`
+    Upper,
+    /// `my file`
+    Lower,
+    /// `My File`
+    Title,
+    /// `my_file`
+    Snake,
+    /// `my-file`
+    Kebab,
+    /// `myFile`
+    Camel,
+}
+
+impl CaseModifier {
+    /// Alternatives of the modifier names for the insertion flag regex
+    pub const NAMES: &'static str = "upper|lower|title|snake|kebab|camel";
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "upper" => Some(Self::Upper),
+            "lower" => Some(Self::Lower),
+            "title" => Some(Self::Title),
+            "snake" => Some(Self::Snake),
+            "kebab" => Some(Self::Kebab),
+            "camel" => Some(Self::Camel),
+            _ => None,
+        }
+    }
+
+    /// Transforms every path component of `text` separately, so directories of the capture are kept
+    pub fn apply(&self, text: &str) -> String {
+        text.split('/')
+            .map(|component| match self {
+                Self::Upper => component.to_uppercase(),
+                Self::Lower => component.to_lowercase(),
+                Self::Title => title_case(component),
+                Self::Snake => words(component).join("_"),
+                Self::Kebab => words(component).join("-"),
+                Self::Camel => camel_case(component),
+            })
+            .collect::<Vec<String>>()
+            .join("/")
+    }
+}
+
+fn capitalized(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first
+            .to_uppercase()
+            .chain(chars.flat_map(char::to_lowercase))
+            .collect(),
+        None => String::new(),
+    }
+}
+
+/// Capitalizes every alphanumeric run, separators are kept
+fn title_case(text: &str) -> String {
+    let mut result = String::new();
+    let mut is_word_start = true;
+    for ch in text.chars() {
+        match (ch.is_alphanumeric(), is_word_start) {
+            (true, true) => result.extend(ch.to_uppercase()),
+            (true, false) => result.extend(ch.to_lowercase()),
+            (false, _) => result.push(ch),
+        }
+        is_word_start = !ch.is_alphanumeric();
+    }
+    result
+}
+
+fn camel_case(text: &str) -> String {
+    words(text)
+        .iter()
+        .enumerate()
+        .map(|(idx, word)| match idx {
+            0 => word.clone(),
+            _ => capitalized(word),
+        })
+        .collect()
+}
+
+/// Lowercase words of the text. Words are separated by not alphanumeric characters and by case changes,
+/// e.g. `HTTPServer error` consists of `http`, `server` and `error`
+fn words(text: &str) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut words = vec![];
+    let mut word = String::new();
+    for (idx, &ch) in chars.iter().enumerate() {
+        if !ch.is_alphanumeric() {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+        let previous = idx.checked_sub(1).map(|idx| chars[idx]);
+        let next = chars.get(idx + 1);
+        let is_case_change = ch.is_uppercase()
+            && previous.is_some_and(|previous| {
+                previous.is_lowercase()
+                    || previous.is_numeric()
+                    || previous.is_uppercase() && next.is_some_and(|next| next.is_lowercase())
+            });
+        if is_case_change && !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+        word.extend(ch.to_lowercase());
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn words_test() {
+        assert_eq!(words(""), Vec::<String>::new());
+        assert_eq!(words("my file"), vec!["my", "file"]);
+        assert_eq!(words("  my--file_"), vec!["my", "file"]);
+        assert_eq!(words("myFile2Name"), vec!["my", "file2", "name"]);
+        assert_eq!(words("HTTPServer error"), vec!["http", "server", "error"]);
+        assert_eq!(words("IMG_0001"), vec!["img", "0001"]);
+    }
+
+    #[test]
+    fn apply_test() {
+        let apply = |name: &str, text: &str| CaseModifier::from_name(name).unwrap().apply(text);
+        assert_eq!(apply("upper", "my File"), "MY FILE");
+        assert_eq!(apply("lower", "JPG"), "jpg");
+        assert_eq!(apply("title", "my FILE-name"), "My File-Name");
+        assert_eq!(apply("snake", "My File-name"), "my_file_name");
+        assert_eq!(apply("kebab", "myFileName"), "my-file-name");
+        assert_eq!(apply("camel", "my file_name"), "myFileName");
+        assert_eq!(apply("snake", "Some Dir/Some File"), "some_dir/some_file");
+        assert_eq!(apply("title", "straße"), "Straße");
+        assert_eq!(CaseModifier::from_name("reverse"), None);
+    }
+}
@@ -0,0 +1,309 @@
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{absolute, Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::mmv::file_utils::move_file;
+use crate::mmv::transaction::Operation;
+use crate::mmv::{RenamePlan, TfrError};
+
+const JOURNAL_FILE_NAME: &str = "journal.jsonl";
+const SAVED_DIRECTORY_NAME: &str = "saved";
+/// Number of the latest batches kept in the journal by default
+const DEFAULT_MAX_BATCHES: usize = 20;
+
+/// State of a file right after the batch has been executed
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+struct Fingerprint {
+    path: String,
+    size: u64,
+    modified: SystemTime,
+}
+
+impl Fingerprint {
+    fn new(path: &str) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(Self {
+            path: path.to_string(),
+            size: metadata.len(),
+            modified: metadata.modified().ok()?,
+        })
+    }
+}
+
+/// Record of a single executed [RenamePlan](RenamePlan). All paths are absolute
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct JournalBatch {
+    pub id: String,
+    /// Execution time in RFC 3339 format
+    pub time: String,
+    /// Renamed `(source, destination)` pairs
+    pub renamed: Vec<(String, String)>,
+    operations: Vec<Operation>,
+    fingerprints: Vec<Fingerprint>,
+}
+
+impl JournalBatch {
+    /// Files overwritten or removed during the batch without being saved, they are not restored on undo
+    pub fn deleted_files(&self) -> impl Iterator<Item = &str> {
+        self.operations
+            .iter()
+            .filter_map(|operation| match operation {
+                Operation::DeletedFile(file) => Some(file.as_str()),
+                _ => None,
+            })
+    }
+}
+
+fn absolute_path(path: &str) -> String {
+    absolute(path).map_or(path.to_string(), |path| path.to_string_lossy().to_string())
+}
+
+/// Persistent history of executed batches, stored as JSON lines in the state directory.
+///
+/// Files overwritten during a batch are deleted, unless they are [kept](Journal::set_keep_overwritten) in the state
+/// directory, so the batch can be completely [undone](Journal::undo). Kept files take disk space until the batch is
+/// undone or dropped from the journal, only the [latest batches](Journal::set_max_batches) are kept
+///
+/// # Examples
+/// ```
+/// use tfr::{ActionWhenRenamedFilePathExists, Journal, TemplateFileRenamer};
+/// let mut tfr = TemplateFileRenamer::new(ActionWhenRenamedFilePathExists::Overwrite);
+/// tfr.set_journal(Journal::new("path/to/state"));
+/// if tfr.rename("path/to/before_*.*", "path/to/after_#1.#2").is_ok() {
+///     let _ = Journal::new("path/to/state").undo(None);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Journal {
+    directory: PathBuf,
+    max_batches: usize,
+    keep_overwritten: bool,
+}
+
+impl Journal {
+    pub fn new(directory: impl AsRef<Path>) -> Self {
+        let directory = directory.as_ref();
+        Self {
+            directory: absolute(directory).unwrap_or(directory.to_path_buf()),
+            max_batches: DEFAULT_MAX_BATCHES,
+            keep_overwritten: false,
+        }
+    }
+
+    /// Sets the number of the latest batches kept in the journal, 20 by default.
+    /// Older batches and their saved files are removed when a new batch is recorded, so they can not be undone
+    pub fn set_max_batches(&mut self, max_batches: usize) {
+        self.max_batches = max_batches
+    }
+
+    /// Sets whether files overwritten or removed during a batch are saved in the state directory to be restored
+    /// on undo, instead of being deleted. Disabled by default
+    pub fn set_keep_overwritten(&mut self, keep_overwritten: bool) {
+        self.keep_overwritten = keep_overwritten
+    }
+
+    pub(crate) fn keeps_overwritten(&self) -> bool {
+        self.keep_overwritten
+    }
+
+    fn journal_path(&self) -> PathBuf {
+        self.directory.join(JOURNAL_FILE_NAME)
+    }
+
+    /// Directory where files overwritten during the batch are saved
+    pub(crate) fn saved_directory(&self, id: &str) -> PathBuf {
+        self.directory.join(SAVED_DIRECTORY_NAME).join(id)
+    }
+
+    /// Returns a new batch id that is not used by recorded batches and saved files
+    pub(crate) fn new_batch_id(&self) -> String {
+        let taken: HashSet<String> = self
+            .batches()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|batch| batch.id)
+            .collect();
+        let prefix = Local::now().format("%Y%m%d-%H%M%S%.3f").to_string();
+        let mut id = prefix.clone();
+        let mut counter = 1;
+        while taken.contains(&id) || self.saved_directory(&id).exists() {
+            id = format!("{prefix}-{counter}");
+            counter += 1;
+        }
+        id
+    }
+
+    pub(crate) fn append(
+        &self,
+        id: String,
+        plan: &RenamePlan,
+        operations: Vec<Operation>,
+    ) -> Result<(), TfrError> {
+        let operations: Vec<Operation> = operations
+            .into_iter()
+            .map(|operation| match operation {
+                Operation::Renamed { from, to } => Operation::Renamed {
+                    from: absolute_path(&from),
+                    to: absolute_path(&to),
+                },
+                Operation::CreatedFile(file) => Operation::CreatedFile(absolute_path(&file)),
+                Operation::CreatedDirectory(directory) => {
+                    Operation::CreatedDirectory(absolute_path(&directory))
+                }
+                Operation::RemovedDirectory(directory) => {
+                    Operation::RemovedDirectory(absolute_path(&directory))
+                }
+                Operation::DeletedFile(file) => Operation::DeletedFile(absolute_path(&file)),
+            })
+            .collect();
+        let performed: HashSet<&str> = operations
+            .iter()
+            .filter_map(|operation| match operation {
+                Operation::Renamed { to, .. } | Operation::CreatedFile(to) => Some(to.as_str()),
+                Operation::CreatedDirectory(_)
+                | Operation::RemovedDirectory(_)
+                | Operation::DeletedFile(_) => None,
+            })
+            .collect();
+        let renamed: Vec<(String, String)> = plan
+            .executed_entries()
+            .map(|entry| {
+                (
+                    absolute_path(&entry.source),
+                    absolute_path(&entry.destination),
+                )
+            })
+            .filter(|(_, destination)| performed.contains(destination.as_str()))
+            .collect();
+        let fingerprints = renamed
+            .iter()
+            .filter_map(|(_, destination)| Fingerprint::new(destination))
+            .collect();
+
+        let batch = JournalBatch {
+            id,
+            time: Local::now().to_rfc3339(),
+            renamed,
+            operations,
+            fingerprints,
+        };
+
+        fs::create_dir_all(&self.directory)?;
+        let mut journal = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.journal_path())?;
+        writeln!(journal, "{}", serde_json::to_string(&batch)?)?;
+        self.drop_oldest()
+    }
+
+    /// Removes batches exceeding the limit and their saved files, the oldest go first
+    fn drop_oldest(&self) -> Result<(), TfrError> {
+        let mut batches = self.batches()?;
+        let excess = batches.len().saturating_sub(self.max_batches);
+        if excess == 0 {
+            return Ok(());
+        }
+        let dropped: Vec<JournalBatch> = batches.drain(..excess).collect();
+        self.write_batches(&batches)?;
+        for batch in dropped {
+            let _ = fs::remove_dir_all(self.saved_directory(&batch.id));
+        }
+        Ok(())
+    }
+
+    fn write_batches(&self, batches: &[JournalBatch]) -> Result<(), TfrError> {
+        let mut journal = String::new();
+        for batch in batches {
+            journal.push_str(&serde_json::to_string(batch)?);
+            journal.push('\n');
+        }
+        fs::write(self.journal_path(), journal)?;
+        Ok(())
+    }
+
+    /// All recorded batches, the oldest goes first
+    pub fn batches(&self) -> Result<Vec<JournalBatch>, TfrError> {
+        if !self.journal_path().exists() {
+            return Ok(vec![]);
+        }
+        let mut batches = vec![];
+        for line in fs::read_to_string(self.journal_path())?.lines() {
+            if !line.trim().is_empty() {
+                batches.push(serde_json::from_str(line)?);
+            }
+        }
+        Ok(batches)
+    }
+
+    /// Restores the state before the batch with the given id, or the last batch if id is None,
+    /// and removes the batch from the journal. [Deleted files](JournalBatch::deleted_files) are not restored
+    ///
+    /// Returns Err([TfrError::ChangedSinceRename](TfrError::ChangedSinceRename)) without changing anything if
+    /// any renamed file was changed or removed, or the path of any renamed file is occupied
+    pub fn undo(&self, id: Option<&str>) -> Result<JournalBatch, TfrError> {
+        let mut batches = self.batches()?;
+        let position = match id {
+            None => batches.len().checked_sub(1),
+            Some(id) => batches.iter().position(|batch| batch.id == id),
+        }
+        .ok_or(TfrError::JournalBatchNotFound(id.map(String::from)))?;
+        let batch = batches.remove(position);
+
+        for fingerprint in &batch.fingerprints {
+            if Fingerprint::new(&fingerprint.path).as_ref() != Some(fingerprint) {
+                return Err(TfrError::ChangedSinceRename(fingerprint.path.clone()));
+            }
+        }
+        let destinations: HashSet<&str> = batch
+            .renamed
+            .iter()
+            .map(|(_, destination)| destination.as_str())
+            .collect();
+        let moved_sources: HashSet<&str> = batch
+            .operations
+            .iter()
+            .filter_map(|operation| match operation {
+                Operation::Renamed { from, .. } => Some(from.as_str()),
+                _ => None,
+            })
+            .collect();
+        for (source, _) in &batch.renamed {
+            if moved_sources.contains(source.as_str())
+                && !destinations.contains(source.as_str())
+                && Path::new(source).exists()
+            {
+                return Err(TfrError::ChangedSinceRename(source.clone()));
+            }
+        }
+        let saved_directory = self.saved_directory(&batch.id);
+        for operation in &batch.operations {
+            if let Operation::Renamed { to, .. } = operation {
+                if Path::new(to).starts_with(&saved_directory) && !Path::new(to).is_file() {
+                    return Err(TfrError::ChangedSinceRename(to.clone()));
+                }
+            }
+        }
+
+        for operation in batch.operations.iter().rev() {
+            match operation {
+                Operation::Renamed { from, to } => move_file(to, from, || {})?,
+                Operation::CreatedFile(file) => fs::remove_file(file)?,
+                Operation::CreatedDirectory(directory) => {
+                    let _ = fs::remove_dir(directory);
+                }
+                Operation::RemovedDirectory(directory) => fs::create_dir_all(directory)?,
+                Operation::DeletedFile(_) => {}
+            }
+        }
+        let _ = fs::remove_dir_all(saved_directory);
+        self.write_batches(&batches)?;
+
+        Ok(batch)
+    }
+}
@@ -0,0 +1,24 @@
+/// Kinds of paths matched by the input template
+///
+/// A matched directory is moved as a whole with all its content, so paths nested in another matched directory
+/// are not matched on their own
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub enum MatchKind {
+    /// Only files are matched
+    #[default]
+    Files,
+    /// Only directories are matched
+    Directories,
+    /// Both files and directories are matched
+    All,
+}
+
+impl MatchKind {
+    pub fn matches_files(&self) -> bool {
+        *self != MatchKind::Directories
+    }
+
+    pub fn matches_directories(&self) -> bool {
+        *self != MatchKind::Files
+    }
+}
@@ -0,0 +1,11 @@
+/// What is inserted instead of a metadata placeholder, e.g. `#{exif:Model}`, if the file lacks the tag
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub enum MetadataFallback {
+    /// The file is left in place
+    #[default]
+    Skip,
+    /// Modification time of the file, formatted with the date format of the placeholder or `%Y-%m-%d`
+    Modified,
+    /// The literal value
+    Default(String),
+}
@@ -1,19 +1,45 @@
+mod auto_rename;
+mod backup_scheme;
+mod conflict_decision;
 mod errors;
 mod file_utils;
+mod journal;
+mod match_kind;
+mod metadata_fallback;
+mod operation_mode;
 mod rename_mod;
+mod rename_notice;
+mod rename_order;
+mod rename_plan;
+mod sort_by;
 mod template_applier;
+mod transaction;
 
+pub use backup_scheme::BackupScheme;
+pub use conflict_decision::{ConflictDecision, ConflictResolution};
 pub use errors::TfrError;
-pub use rename_mod::ActionWhenRenamedFilePathExists;
+pub use journal::{Journal, JournalBatch};
+pub use match_kind::MatchKind;
+pub use metadata_fallback::MetadataFallback;
+pub use operation_mode::OperationMode;
+pub use rename_mod::{ActionWhenRenamedFilePathExists, WhenIdentical};
+pub use rename_notice::RenameNotice;
+pub use rename_plan::{RenameAction, RenameEntry, RenamePlan};
+pub use sort_by::SortBy;
 
 use std::fs;
-use std::fs::create_dir_all;
-use std::path::Path;
+use std::io;
+use std::path::{absolute, Path};
 
-use crate::ActionWhenRenamedFilePathExists::Overwrite;
+use auto_rename::DEFAULT_AUTO_RENAME_PATTERN;
+use file_utils::relative_path;
+use rename_order::order_renames;
 use template_applier::apply_template;
+use transaction::Transaction;
 
 type CallbackHandler<'ch> = dyn Fn(usize, usize, Option<&str>, Option<&str>) + 'ch;
+type NoticeHandler<'ch> = dyn Fn(RenameNotice) + 'ch;
+type ConflictHandler<'ch> = dyn Fn(&str, &str) -> ConflictDecision + 'ch;
 
 /// Provides template file paths renaming.
 ///
@@ -31,17 +57,132 @@ type CallbackHandler<'ch> = dyn Fn(usize, usize, Option<&str>, Option<&str>) + '
 #[derive(Default)]
 pub struct TemplateFileRenamer<'ch> {
     rename_mod: ActionWhenRenamedFilePathExists,
+    backup_scheme: BackupScheme,
+    auto_rename_pattern: String,
+    when_identical: WhenIdentical,
+    operation_mode: OperationMode,
+    match_kind: MatchKind,
+    merge_directories: bool,
+    sort_by: SortBy,
+    reverse_order: bool,
+    metadata_fallback: MetadataFallback,
     callback_handler: Option<Box<CallbackHandler<'ch>>>,
+    notice_handler: Option<Box<NoticeHandler<'ch>>>,
+    conflict_handler: Option<Box<ConflictHandler<'ch>>>,
+    transactional: bool,
+    journal: Option<Journal>,
 }
 
 impl<'ch> TemplateFileRenamer<'ch> {
     pub fn new(rename_mod: ActionWhenRenamedFilePathExists) -> Self {
         Self {
             rename_mod,
+            backup_scheme: BackupScheme::default(),
+            auto_rename_pattern: DEFAULT_AUTO_RENAME_PATTERN.to_string(),
+            when_identical: WhenIdentical::Ignore,
+            operation_mode: OperationMode::Move,
+            match_kind: MatchKind::Files,
+            merge_directories: false,
+            sort_by: SortBy::Natural,
+            reverse_order: false,
+            metadata_fallback: MetadataFallback::Skip,
             callback_handler: None,
+            notice_handler: None,
+            conflict_handler: None,
+            transactional: false,
+            journal: None,
         }
     }
 
+    /// Sets naming of backups made in the `Backup` mod, the existing file is moved to `<path>~` by default
+    ///
+    /// Every backup is reported to the [notice handler](TemplateFileRenamer::set_notice_handler)
+    pub fn set_backup_scheme(&mut self, backup_scheme: BackupScheme) {
+        self.backup_scheme = backup_scheme
+    }
+
+    /// Sets the pattern of free file names chosen in the `AutoRename` mod, `#name (#n).#ext` by default
+    ///
+    /// `#name` is replaced with the file name without the extension, `#ext` with the extension and `#n` with
+    /// the smallest counter giving a free path. The pattern must contain `#n`
+    ///
+    /// # Example
+    /// ```
+    /// use tfr::{ActionWhenRenamedFilePathExists, TemplateFileRenamer};
+    /// let mut tfr = TemplateFileRenamer::new(ActionWhenRenamedFilePathExists::AutoRename);
+    /// tfr.set_auto_rename_pattern("#name_#n.#ext");
+    /// ```
+    pub fn set_auto_rename_pattern(&mut self, auto_rename_pattern: &str) {
+        self.auto_rename_pattern = auto_rename_pattern.to_string()
+    }
+
+    /// Sets the behavior when an existing file has the same content as the renamed file, content is not compared
+    /// by default
+    ///
+    /// Contents are compared only if sizes are equal. Removed redundant files are reported to the
+    /// [notice handler](TemplateFileRenamer::set_notice_handler)
+    pub fn set_when_identical(&mut self, when_identical: WhenIdentical) {
+        self.when_identical = when_identical
+    }
+
+    /// Sets what is done with matched files, they are moved by default
+    ///
+    /// Conflict rules and the callback handler are the same for all modes
+    pub fn set_operation_mode(&mut self, operation_mode: OperationMode) {
+        self.operation_mode = operation_mode
+    }
+
+    /// Sets kinds of paths matched by the input template, only files are matched by default
+    ///
+    /// Matched directories are moved with all their content. If the destination of a directory exists, the
+    /// directory is skipped in the `Skip` mod and is a conflict otherwise
+    pub fn set_match_kind(&mut self, match_kind: MatchKind) {
+        self.match_kind = match_kind
+    }
+
+    /// Enables merging of matched directories into existing destination directories
+    ///
+    /// The content of the directory is moved into the existing directory file by file, the renaming mod is
+    /// applied to every existing file. Emptied source directories are removed after renaming
+    pub fn set_merge_directories(&mut self, merge_directories: bool) {
+        self.merge_directories = merge_directories
+    }
+
+    /// Sets the order of matched paths, they are sorted in the natural order by default
+    ///
+    /// Entries of the plan, the callback handler and counters of the output template follow this order
+    pub fn set_sort_by(&mut self, sort_by: SortBy) {
+        self.sort_by = sort_by
+    }
+
+    /// Reverses the order of matched paths
+    pub fn set_reverse_order(&mut self, reverse_order: bool) {
+        self.reverse_order = reverse_order
+    }
+
+    /// Sets what is inserted instead of a metadata placeholder of the output template, e.g. `#{exif:Model}`, if
+    /// the file lacks the tag. Such files are skipped by default
+    pub fn set_metadata_fallback(&mut self, metadata_fallback: MetadataFallback) {
+        self.metadata_fallback = metadata_fallback
+    }
+
+    /// Enables all-or-nothing execution
+    ///
+    /// If any error occurs, all already performed renames are reverted, created directories are removed and
+    /// [TfrError::RolledBack](TfrError::RolledBack) is returned. Overwritten files are deleted only after all
+    /// files have been renamed
+    pub fn set_transactional(&mut self, transactional: bool) {
+        self.transactional = transactional
+    }
+
+    /// Records every executed batch to the [Journal](Journal), so it can be undone later
+    ///
+    /// Overwritten files are moved to the journal state directory instead of being deleted, if the journal
+    /// [keeps them](Journal::set_keep_overwritten)
+    pub fn set_journal(&mut self, journal: Journal) {
+        self.journal = Some(journal)
+    }
+
     /// # Signature
     /// dyn Fn(processed: usize, total: usize, old_filepath: Option<&str>, new_filepath: Option<&str>)
     ///
@@ -70,47 +211,237 @@ impl<'ch> TemplateFileRenamer<'ch> {
         self.callback_handler = Some(Box::new(callback_handler))
     }
 
+    /// Sets the handler of notable events of renaming, see [RenameNotice](RenameNotice)
+    ///
+    /// # Example
+    /// ```
+    /// use tfr::{ActionWhenRenamedFilePathExists, RenameNotice, TemplateFileRenamer};
+    /// let mut tfr = TemplateFileRenamer::new(ActionWhenRenamedFilePathExists::Terminate);
+    /// tfr.set_notice_handler(|notice: RenameNotice| match notice {
+    ///     RenameNotice::CrossDeviceCopy { from, to } => println!("Copying {from} to {to}"),
+    ///     RenameNotice::BackedUp { path, backup } => println!("Backed up {path} to {backup}"),
+    ///     _ => {}
+    /// });
+    /// ```
+    pub fn set_notice_handler(&mut self, notice_handler: impl Fn(RenameNotice) + 'ch) {
+        self.notice_handler = Some(Box::new(notice_handler))
+    }
+
+    /// Sets the handler deciding what to do with every file renamed to an existing path in the `Ask` mod.
+    /// It is called with the source and destination paths while the plan is built
+    ///
+    /// # Example
+    /// ```
+    /// use tfr::{ActionWhenRenamedFilePathExists, ConflictDecision, ConflictResolution, TemplateFileRenamer};
+    /// let mut tfr = TemplateFileRenamer::new(ActionWhenRenamedFilePathExists::Ask);
+    /// tfr.set_conflict_handler(|source: &str, _destination: &str| match source.ends_with(".tmp") {
+    ///     true => ConflictDecision::once(ConflictResolution::Skip),
+    ///     false => ConflictDecision::for_all(ConflictResolution::Rename),
+    /// });
+    /// ```
+    pub fn set_conflict_handler(
+        &mut self,
+        conflict_handler: impl Fn(&str, &str) -> ConflictDecision + 'ch,
+    ) {
+        self.conflict_handler = Some(Box::new(conflict_handler))
+    }
+
+    fn notify(&self, notice: RenameNotice) {
+        if let Some(notice_handler) = &self.notice_handler {
+            notice_handler(notice)
+        }
+    }
+
     fn start(&self, total: usize) {
-        if self.callback_handler.is_some() {
-            (self.callback_handler.as_ref().unwrap())(0, total, None, None)
+        if let Some(callback_handler) = &self.callback_handler {
+            callback_handler(0, total, None, None)
         }
     }
 
     fn callback(&self, current: usize, total: usize, old_filepath: &str, new_filepath: &str) {
-        if self.callback_handler.is_some() {
-            (self.callback_handler.as_ref().unwrap())(
-                current,
-                total,
-                Some(old_filepath),
-                Some(new_filepath),
-            )
+        if let Some(callback_handler) = &self.callback_handler {
+            callback_handler(current, total, Some(old_filepath), Some(new_filepath))
         }
     }
 
-    /// Returns Ok(()) if all files matching the template have been successfully renamed
+    /// Builds the [RenamePlan](RenamePlan) for the templates without changing the filesystem
     ///
-    /// Returns Err([TfrError](TfrError)) if any error occurred during renaming
-    pub fn rename(
+    /// Returns Err([TfrError](TfrError)) if templates are incorrect. Conflicts with existing paths are recorded in
+    /// the plan, see [RenamePlan::check](RenamePlan::check)
+    pub fn plan(
         &self,
         input_file_template: &str,
         output_file_template: &str,
+    ) -> Result<RenamePlan, TfrError> {
+        apply_template(input_file_template, output_file_template, self)
+    }
+
+    /// Performs all executed entries of the plan in the [OperationMode](OperationMode) of the plan
+    ///
+    /// Entries are reordered when destinations of some entries are sources of others, cycles like swaps
+    /// are renamed through temporary paths
+    ///
+    /// Returns Err([TfrError](TfrError)) if the plan contains conflicts, the destination of a renamed entry
+    /// appeared after planning or any error occurred during renaming
+    pub fn execute(&self, plan: &RenamePlan) -> Result<(), TfrError> {
+        plan.check()?;
+
+        let on_copy =
+            |from: &str, to: &str| self.notify(RenameNotice::CrossDeviceCopy { from, to });
+        let keeps_overwritten = self
+            .journal
+            .as_ref()
+            .is_some_and(Journal::keeps_overwritten);
+        let mut transaction = Transaction::new(self.transactional || keeps_overwritten, &on_copy);
+        let result = self.execute_steps(plan, &mut transaction);
+        if let Err(error) = result {
+            if self.transactional {
+                return Err(TfrError::RolledBack(
+                    Box::new(error),
+                    transaction.rollback(),
+                ));
+            }
+            self.commit(plan, transaction)?;
+            return Err(error);
+        }
+        self.commit(plan, transaction)
+    }
+
+    /// Finishes the transaction and records performed operations to the journal
+    fn commit(&self, plan: &RenamePlan, transaction: Transaction) -> Result<(), TfrError> {
+        let Some(journal) = &self.journal else {
+            transaction.commit(None)?;
+            return Ok(());
+        };
+        let id = journal.new_batch_id();
+        let saved_directory = journal
+            .keeps_overwritten()
+            .then(|| journal.saved_directory(&id));
+        let operations = transaction.commit(saved_directory.as_deref())?;
+        if !operations.is_empty() {
+            journal.append(id, plan, operations)?;
+        }
+        Ok(())
+    }
+
+    /// Moves, copies or links the source of the entry to `to` according to the operation mode. Directories are
+    /// copied or hard linked file by file
+    ///
+    /// `to` is either the destination or a temporary path, so relative links are always computed from
+    /// the destination
+    fn transfer(
+        operation_mode: OperationMode,
+        transaction: &mut Transaction,
+        entry: &RenameEntry,
+        to: &str,
+    ) -> io::Result<()> {
+        let from = entry.source.as_str();
+        let is_directory = Path::new(from).is_dir();
+        match operation_mode {
+            OperationMode::Move => transaction.rename(from, to),
+            OperationMode::Copy | OperationMode::HardLink if is_directory => {
+                transaction.copy_directory(from, to, operation_mode == OperationMode::HardLink)
+            }
+            OperationMode::Copy => transaction.copy(from, to),
+            OperationMode::HardLink => transaction.hard_link(from, to),
+            OperationMode::SymLink { relative: false } => {
+                transaction.symlink(&absolute(from)?.to_string_lossy(), to)
+            }
+            OperationMode::SymLink { relative: true } => {
+                let parent = Path::new(&entry.destination).parent();
+                let parent = parent.unwrap_or(Path::new("")).to_string_lossy();
+                transaction.symlink(&relative_path(from, &parent)?, to)
+            }
+        }
+    }
+
+    fn execute_steps(
+        &self,
+        plan: &RenamePlan,
+        transaction: &mut Transaction,
     ) -> Result<(), TfrError> {
-        let applied_new_filepaths =
-            apply_template(input_file_template, output_file_template, &self.rename_mod)?;
+        let total = plan.executed_entries().count();
+        self.start(total);
 
-        self.start(applied_new_filepaths.len());
+        // Redundant files are removed first, their paths may be destinations of other entries
+        for entry in plan.entries() {
+            if entry.action == RenameAction::RemoveSource {
+                transaction.remove_file(&entry.source)?;
+                self.notify(RenameNotice::RemovedIdentical {
+                    path: &entry.source,
+                    identical: &entry.destination,
+                });
+            }
+        }
 
-        for (idx, (first, second)) in applied_new_filepaths.iter().enumerate() {
-            if let Some(parent) = Path::new(second).parent() {
-                create_dir_all(parent)?;
+        let steps = order_renames(plan.entries(), |path| !Path::new(path).exists());
+        let mut processed = 0;
+        for step in steps {
+            let entry = &plan.entries()[step.entry];
+            if step.is_temporary {
+                Self::transfer(plan.operation_mode(), transaction, entry, &step.to)?;
+                continue;
             }
 
-            if self.rename_mod == Overwrite && Path::new(second).is_file() {
-                fs::remove_file(second)?;
+            let destination = Path::new(&entry.destination);
+            transaction.create_parent_directories(&entry.destination)?;
+
+            match (entry.action, &entry.backup) {
+                (RenameAction::Overwrite, _) if destination.is_file() => {
+                    transaction.remove_file(&entry.destination)?
+                }
+                (RenameAction::Backup, Some(backup)) if destination.is_file() => {
+                    if Path::new(backup).is_file() {
+                        transaction.remove_file(backup)?;
+                    }
+                    transaction.rename(&entry.destination, backup)?;
+                    self.notify(RenameNotice::BackedUp {
+                        path: &entry.destination,
+                        backup,
+                    });
+                }
+                (RenameAction::Rename, _) if destination.exists() => {
+                    return Err(TfrError::ExistingPath(
+                        entry.destination.clone(),
+                        destination.is_file(),
+                    ))
+                }
+                _ => {}
+            }
+            match step.from == entry.source {
+                true => Self::transfer(
+                    plan.operation_mode(),
+                    transaction,
+                    entry,
+                    &entry.destination,
+                )?,
+                // Finishing the entry transferred to a temporary path
+                false => transaction.rename(&step.from, &entry.destination)?,
+            }
+
+            processed += 1;
+            self.callback(processed, total, &entry.source, &entry.destination);
+        }
+
+        if plan.operation_mode().moves_sources() {
+            for directory in plan.merged_directories().iter().rev() {
+                if fs::read_dir(directory)?.next().is_none() {
+                    transaction.remove_directory(directory)?;
+                }
             }
-            fs::rename(first, second)?;
-            self.callback(idx + 1, applied_new_filepaths.len(), first, second);
         }
         Ok(())
     }
+
+    /// Returns Ok(()) if all files matching the template have been successfully renamed
+    ///
+    /// Returns Err([TfrError](TfrError)) if any error occurred during renaming
+    pub fn rename(
+        &self,
+        input_file_template: &str,
+        output_file_template: &str,
+    ) -> Result<(), TfrError> {
+        let plan = self.plan(input_file_template, output_file_template)?;
+        self.execute(&plan)
+    }
 }
@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+/// What `TemplateFileRenamer` does with matched files
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum OperationMode {
+    /// Files are moved to the destination paths
+    #[default]
+    Move,
+    /// Files are copied to the destination paths, matched files are left intact
+    Copy,
+    /// Hard links to matched files are created at the destination paths
+    HardLink,
+    /// Symbolic links to matched files are created at the destination paths. Relative links are computed from
+    /// the parent directory of the destination, so they remain valid when the whole tree is moved
+    SymLink { relative: bool },
+}
+
+impl OperationMode {
+    /// Returns true if matched files are removed from their paths
+    pub fn moves_sources(&self) -> bool {
+        *self == OperationMode::Move
+    }
+}
@@ -1,8 +1,40 @@
 /// Possible behavior of `TemplateFileRenamer` when a new file path already exists
+///
+/// `Backup` moves the existing file aside before renaming, like `mv --backup`. The backup path is defined by
+/// [BackupScheme](crate::BackupScheme)
+///
+/// `AutoRename` renames the file to the first free path given by the
+/// [auto rename pattern](crate::TemplateFileRenamer::set_auto_rename_pattern), e.g. `photo (1).jpg`. Paths of
+/// existing files and destinations of other files of the same batch are not free
+///
+/// `OverwriteIfNewer` and `OverwriteIfLarger` overwrite the existing file only if the renamed file is newer
+/// by modification time or larger, otherwise the file is skipped. If several files are renamed to the same
+/// path, the newest or the largest one is renamed
+///
+/// `Ask` calls the [conflict handler](crate::TemplateFileRenamer::set_conflict_handler) for every existing file
+/// while planning. Without the handler, existing files are conflicts. Existing directories and collisions
+/// of files of the same batch are always conflicts
 #[derive(Default, Eq, PartialEq)]
 pub enum ActionWhenRenamedFilePathExists {
     #[default]
     Terminate,
     Skip,
     Overwrite,
+    Backup,
+    AutoRename,
+    OverwriteIfNewer,
+    OverwriteIfLarger,
+    Ask,
+}
+
+/// Behavior of `TemplateFileRenamer` when a new file path already exists and has the same content
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub enum WhenIdentical {
+    /// Content is not compared, [ActionWhenRenamedFilePathExists](ActionWhenRenamedFilePathExists) is applied
+    #[default]
+    Ignore,
+    /// The file is left in place
+    Skip,
+    /// The redundant file is removed, if files are moved
+    RemoveSource,
 }
@@ -0,0 +1,12 @@
+/// Notable events of renaming reported to the
+/// [notice handler](crate::TemplateFileRenamer::set_notice_handler). New kinds of events may be added
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum RenameNotice<'a> {
+    /// The file or the directory is moved to another filesystem by copying and removing the source, it may take a while
+    CrossDeviceCopy { from: &'a str, to: &'a str },
+    /// The existing file is moved aside to `backup` in the `Backup` mod
+    BackedUp { path: &'a str, backup: &'a str },
+    /// The matched file is removed, because the existing file `identical` has the same content
+    RemovedIdentical { path: &'a str, identical: &'a str },
+}
@@ -0,0 +1,192 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::mmv::RenameEntry;
+
+/// Single filesystem rename performed while executing the plan
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RenameStep {
+    /// Index of the plan entry
+    pub entry: usize,
+    pub from: String,
+    pub to: String,
+    /// Source is moved to a temporary path to break a cycle, the entry is finished by a later step
+    pub is_temporary: bool,
+}
+
+/// Returns the first free path of the form `<path>.tfr-tmp<counter>`
+pub fn temporary_path(source: &str, is_free: &impl Fn(&str) -> bool) -> String {
+    let mut counter = 0;
+    loop {
+        let candidate = format!("{source}.tfr-tmp{counter}");
+        if is_free(&candidate) {
+            return candidate;
+        }
+        counter += 1;
+    }
+}
+
+/// Orders renames of executed entries, so no entry is renamed to a path not yet freed by another entry.
+///
+/// Executed entries have unique sources and destinations, so they form disjoint chains (`a -> b`, `b -> c`)
+/// and cycles (`a -> b`, `b -> a`). Chains are renamed from the end, cycles are broken by moving one source
+/// to a temporary path, which is checked with `is_free`
+pub fn order_renames(entries: &[RenameEntry], is_free: impl Fn(&str) -> bool) -> Vec<RenameStep> {
+    let executed: Vec<usize> = (0..entries.len())
+        .filter(|&idx| entries[idx].is_executed())
+        .collect();
+    let by_source: HashMap<&str, usize> = executed
+        .iter()
+        .map(|&idx| (entries[idx].source.as_str(), idx))
+        .collect();
+    let by_destination: HashMap<&str, usize> = executed
+        .iter()
+        .map(|&idx| (entries[idx].destination.as_str(), idx))
+        .collect();
+    let plan_paths: HashSet<&str> = executed
+        .iter()
+        .flat_map(|&idx| {
+            [
+                entries[idx].source.as_str(),
+                entries[idx].destination.as_str(),
+            ]
+        })
+        .collect();
+    let is_free = |path: &str| !plan_paths.contains(path) && is_free(path);
+
+    // Entry which must be renamed before the given one, because it occupies its destination
+    let blocker = |idx: usize| {
+        by_source
+            .get(entries[idx].destination.as_str())
+            .copied()
+            .filter(|&blocker| blocker != idx)
+    };
+    // Entry waiting for the given one, because its destination is the source of the given one
+    let dependent = |idx: usize| {
+        by_destination
+            .get(entries[idx].source.as_str())
+            .copied()
+            .filter(|&dependent| dependent != idx)
+    };
+
+    let final_step = |idx: usize, from: &str| RenameStep {
+        entry: idx,
+        from: from.to_string(),
+        to: entries[idx].destination.clone(),
+        is_temporary: false,
+    };
+
+    let mut steps = vec![];
+    let mut done = vec![false; entries.len()];
+
+    for &first in executed.iter().filter(|&&idx| blocker(idx).is_none()) {
+        let mut current = Some(first);
+        while let Some(idx) = current {
+            done[idx] = true;
+            steps.push(final_step(idx, &entries[idx].source));
+            current = dependent(idx);
+        }
+    }
+
+    for &first in &executed {
+        if done[first] {
+            continue;
+        }
+        let temporary = temporary_path(&entries[first].source, &is_free);
+        steps.push(RenameStep {
+            entry: first,
+            from: entries[first].source.clone(),
+            to: temporary.clone(),
+            is_temporary: true,
+        });
+
+        let mut current = dependent(first);
+        while let Some(idx) = current.filter(|&idx| idx != first) {
+            done[idx] = true;
+            steps.push(final_step(idx, &entries[idx].source));
+            current = dependent(idx);
+        }
+        done[first] = true;
+        steps.push(final_step(first, &temporary));
+    }
+
+    steps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mmv::RenameAction;
+
+    fn entry(source: &str, destination: &str) -> RenameEntry {
+        RenameEntry {
+            source: source.to_string(),
+            destination: destination.to_string(),
+            action: RenameAction::Rename,
+            backup: None,
+        }
+    }
+
+    fn moves(steps: &[RenameStep]) -> Vec<(&str, &str)> {
+        steps
+            .iter()
+            .map(|step| (step.from.as_str(), step.to.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn independent_test() {
+        let entries = [entry("a", "x"), entry("b", "y")];
+        let steps = order_renames(&entries, |_| true);
+        assert_eq!(moves(&steps), [("a", "x"), ("b", "y")]);
+        assert!(steps.iter().all(|step| !step.is_temporary));
+    }
+
+    #[test]
+    fn chain_test() {
+        let entries = [entry("1", "2"), entry("2", "3"), entry("3", "4")];
+        let steps = order_renames(&entries, |_| true);
+        assert_eq!(moves(&steps), [("3", "4"), ("2", "3"), ("1", "2")]);
+        assert_eq!(
+            steps.iter().map(|step| step.entry).collect::<Vec<_>>(),
+            [2, 1, 0]
+        );
+    }
+
+    #[test]
+    fn swap_test() {
+        let entries = [entry("x_y", "y_x"), entry("y_x", "x_y")];
+        let steps = order_renames(&entries, |_| true);
+        assert_eq!(
+            moves(&steps),
+            [
+                ("x_y", "x_y.tfr-tmp0"),
+                ("y_x", "x_y"),
+                ("x_y.tfr-tmp0", "y_x")
+            ]
+        );
+        assert!(steps[0].is_temporary);
+    }
+
+    #[test]
+    fn cycle_with_occupied_temporary_path_test() {
+        let entries = [entry("a", "b"), entry("b", "c"), entry("c", "a")];
+        let steps = order_renames(&entries, |path| path != "a.tfr-tmp0");
+        assert_eq!(
+            moves(&steps),
+            [
+                ("a", "a.tfr-tmp1"),
+                ("c", "a"),
+                ("b", "c"),
+                ("a.tfr-tmp1", "b")
+            ]
+        );
+    }
+
+    #[test]
+    fn not_executed_entries_test() {
+        let mut skipped = entry("b", "a");
+        skipped.action = RenameAction::Skip;
+        let entries = [entry("a", "b"), skipped];
+        assert_eq!(moves(&order_renames(&entries, |_| true)), [("a", "b")]);
+    }
+}
@@ -0,0 +1,209 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::mmv::{OperationMode, TfrError};
+
+/// Decision taken by the planner for a single matched file
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum RenameAction {
+    /// Destination path is free, the file is renamed
+    Rename,
+    /// Destination file exists and is replaced with the renamed file
+    Overwrite,
+    /// Destination file exists and is moved to the [backup](RenameEntry::backup) path before renaming
+    Backup,
+    /// Destination path exists, the file is left in place
+    Skip,
+    /// Destination path exists and renaming can not be performed
+    Conflict,
+    /// Destination file has the same content, the redundant file is removed
+    RemoveSource,
+}
+
+/// Single source/destination pair of the [RenamePlan](RenamePlan)
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct RenameEntry {
+    pub source: String,
+    pub destination: String,
+    pub action: RenameAction,
+    /// Path where the existing destination file is moved to, if `action` is `Backup`
+    #[serde(default)]
+    pub backup: Option<String>,
+}
+
+impl RenameEntry {
+    /// Returns true if the file is renamed when the plan is executed
+    pub fn is_executed(&self) -> bool {
+        matches!(
+            self.action,
+            RenameAction::Rename | RenameAction::Overwrite | RenameAction::Backup
+        )
+    }
+
+    /// Returns true if the file is renamed or removed when the plan is executed
+    pub fn frees_source(&self) -> bool {
+        self.is_executed() || self.action == RenameAction::RemoveSource
+    }
+}
+
+/// Result of matching templates against the filesystem. Building a plan does not change the filesystem.
+///
+/// The plan can be inspected, serialized, filtered and later executed by
+/// [TemplateFileRenamer::execute](crate::TemplateFileRenamer::execute)
+///
+/// # Examples
+/// ```
+/// use tfr::{ActionWhenRenamedFilePathExists, RenameAction, TemplateFileRenamer};
+/// let tfr = TemplateFileRenamer::new(ActionWhenRenamedFilePathExists::Skip);
+/// if let Ok(mut plan) = tfr.plan("path/to/before_*.*", "path/to/after_#1.#2") {
+///     plan.retain(|entry| !entry.source.ends_with(".tmp"));
+///     for entry in plan.entries() {
+///         println!("{:?}: {} -> {}", entry.action, entry.source, entry.destination);
+///     }
+///     let _ = tfr.execute(&plan);
+/// }
+/// ```
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct RenamePlan {
+    entries: Vec<RenameEntry>,
+    created_directories: Vec<String>,
+    #[serde(default)]
+    operation_mode: OperationMode,
+    #[serde(default)]
+    merged_directories: Vec<String>,
+}
+
+impl RenamePlan {
+    pub fn new(entries: Vec<RenameEntry>, operation_mode: OperationMode) -> Self {
+        let mut plan = Self {
+            entries,
+            created_directories: vec![],
+            operation_mode,
+            merged_directories: vec![],
+        };
+        plan.update_created_directories();
+        plan
+    }
+
+    /// What is done with matched files when the plan is executed
+    pub fn operation_mode(&self) -> OperationMode {
+        self.operation_mode
+    }
+
+    /// All matched files with the decisions taken for them
+    pub fn entries(&self) -> &[RenameEntry] {
+        &self.entries
+    }
+
+    /// Entries that change the filesystem when the plan is executed
+    pub fn executed_entries(&self) -> impl Iterator<Item = &RenameEntry> {
+        self.entries.iter().filter(|entry| entry.is_executed())
+    }
+
+    /// Entries whose destination path exists and can not be replaced
+    pub fn conflicts(&self) -> impl Iterator<Item = &RenameEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.action == RenameAction::Conflict)
+    }
+
+    /// Not existing directories that are created when the plan is executed, parents go first
+    pub fn created_directories(&self) -> &[String] {
+        &self.created_directories
+    }
+
+    /// Source directories merged into existing destination directories, parents go first.
+    /// Moving their content, these directories are removed if they have been emptied
+    pub fn merged_directories(&self) -> &[String] {
+        &self.merged_directories
+    }
+
+    pub(crate) fn set_merged_directories(&mut self, merged_directories: Vec<String>) {
+        self.merged_directories = merged_directories
+    }
+
+    /// Keeps only entries for which `predicate` returns true.
+    ///
+    /// Entries renamed to existing paths freed by removed entries become conflicts, e.g. `a -> b` is a conflict
+    /// if `b -> c` is removed
+    pub fn retain(&mut self, predicate: impl FnMut(&RenameEntry) -> bool) {
+        self.entries.retain(predicate);
+        self.update_freed_conflicts();
+        self.update_created_directories();
+    }
+
+    /// Marks entries renamed to existing paths that are not freed by other entries as conflicts, until
+    /// every freed path is freed by an entry that is still executed
+    fn update_freed_conflicts(&mut self) {
+        loop {
+            let freed_paths: HashSet<String> = match self.operation_mode.moves_sources() {
+                true => self
+                    .entries
+                    .iter()
+                    .filter(|entry| entry.frees_source())
+                    .map(|entry| entry.source.clone())
+                    .collect(),
+                false => HashSet::new(),
+            };
+            let mut is_changed = false;
+            for entry in &mut self.entries {
+                let is_occupied = Path::new(&entry.destination).exists()
+                    && !freed_paths.contains(&entry.destination);
+                if entry.action == RenameAction::Rename && is_occupied {
+                    entry.action = RenameAction::Conflict;
+                    is_changed = true;
+                }
+            }
+            if !is_changed {
+                return;
+            }
+        }
+    }
+
+    /// Source paths of all entries renamed to `destination`
+    pub fn sources_of(&self, destination: &str) -> Vec<&str> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.destination == destination)
+            .map(|entry| entry.source.as_str())
+            .collect()
+    }
+
+    /// Returns Err for the first conflicting entry:
+    /// [TfrError::DestinationCollision](TfrError::DestinationCollision) if several entries have its destination,
+    /// [TfrError::ExistingPath](TfrError::ExistingPath) otherwise
+    pub fn check(&self) -> Result<(), TfrError> {
+        let Some(entry) = self.conflicts().next() else {
+            return Ok(());
+        };
+        let sources = self.sources_of(&entry.destination);
+        if sources.len() > 1 {
+            return Err(TfrError::DestinationCollision(
+                entry.destination.clone(),
+                sources.into_iter().map(String::from).collect(),
+            ));
+        }
+        Err(TfrError::ExistingPath(
+            entry.destination.clone(),
+            Path::new(&entry.destination).is_file(),
+        ))
+    }
+
+    fn update_created_directories(&mut self) {
+        let mut created_directories: Vec<String> = vec![];
+        for entry in self.entries.iter().filter(|entry| entry.is_executed()) {
+            let mut missing: Vec<String> = Path::new(&entry.destination)
+                .ancestors()
+                .skip(1)
+                .filter(|ancestor| !ancestor.as_os_str().is_empty())
+                .take_while(|ancestor| !ancestor.exists())
+                .map(|ancestor| ancestor.to_string_lossy().to_string())
+                .filter(|ancestor| !created_directories.contains(ancestor))
+                .collect();
+            missing.reverse();
+            created_directories.append(&mut missing);
+        }
+        self.created_directories = created_directories;
+    }
+}
@@ -0,0 +1,116 @@
+use std::cmp::Ordering;
+use std::fs;
+
+use crate::mmv::file_utils::FileTime;
+
+/// Order of matched paths. Entries of the plan, the callback and counters follow this order
+///
+/// Paths with equal keys are ordered by their natural order
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub enum SortBy {
+    /// Byte order of paths
+    Name,
+    /// Numbers of paths are compared by their values, so `img2` precedes `img10`
+    #[default]
+    Natural,
+    /// Modification time, oldest first
+    Modified,
+    /// Creation time, oldest first. Modification time is used where the creation time is not available
+    Created,
+    /// Inode change time on Unix, oldest first. Modification time is used on other platforms
+    Changed,
+    /// Size in bytes, smallest first
+    Size,
+}
+
+/// Sorts paths by `sort_by`, in the descending order if `reverse` is set
+pub fn sort_paths(paths: &mut [String], sort_by: SortBy, reverse: bool) {
+    let file_time = match sort_by {
+        SortBy::Created => FileTime::Created,
+        SortBy::Changed => FileTime::Changed,
+        _ => FileTime::Modified,
+    };
+    match sort_by {
+        SortBy::Name => paths.sort(),
+        SortBy::Natural => paths.sort_by(|a, b| natural_cmp(a, b)),
+        SortBy::Modified | SortBy::Created | SortBy::Changed => {
+            paths.sort_by(|a, b| natural_cmp(a, b));
+            paths.sort_by_cached_key(|path| file_time.of(path));
+        }
+        SortBy::Size => {
+            paths.sort_by(|a, b| natural_cmp(a, b));
+            paths.sort_by_cached_key(|path| fs::metadata(path).map(|metadata| metadata.len()).ok());
+        }
+    }
+    if reverse {
+        paths.reverse();
+    }
+}
+
+fn digits_count(text: &str) -> usize {
+    text.find(|ch: char| !ch.is_ascii_digit())
+        .unwrap_or(text.len())
+}
+
+/// Compares texts like a human does: runs of digits are compared by their numeric values, other
+/// characters one by one. Numbers with leading zeros follow equal numbers without them
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut rest_a, mut rest_b) = (a, b);
+    loop {
+        let (Some(ch_a), Some(ch_b)) = (rest_a.chars().next(), rest_b.chars().next()) else {
+            return rest_a.len().cmp(&rest_b.len());
+        };
+        if !ch_a.is_ascii_digit() || !ch_b.is_ascii_digit() {
+            if ch_a != ch_b {
+                return ch_a.cmp(&ch_b);
+            }
+            rest_a = &rest_a[ch_a.len_utf8()..];
+            rest_b = &rest_b[ch_b.len_utf8()..];
+            continue;
+        }
+        let (number_a, tail_a) = rest_a.split_at(digits_count(rest_a));
+        let (number_b, tail_b) = rest_b.split_at(digits_count(rest_b));
+        let value_a = number_a.trim_start_matches('0');
+        let value_b = number_b.trim_start_matches('0');
+        let ordering = value_a
+            .len()
+            .cmp(&value_b.len())
+            .then_with(|| value_a.cmp(value_b))
+            .then_with(|| number_a.len().cmp(&number_b.len()));
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+        (rest_a, rest_b) = (tail_a, tail_b);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn natural_cmp_test() {
+        assert_eq!(natural_cmp("img2.jpg", "img10.jpg"), Ordering::Less);
+        assert_eq!(natural_cmp("img10.jpg", "img2.jpg"), Ordering::Greater);
+        assert_eq!(natural_cmp("img2.jpg", "img2.jpg"), Ordering::Equal);
+        assert_eq!(natural_cmp("img2.jpg", "img02.jpg"), Ordering::Less);
+        assert_eq!(natural_cmp("img.jpg", "img1.jpg"), Ordering::Less);
+        assert_eq!(natural_cmp("a/b10", "a/b9/c"), Ordering::Greater);
+        assert_eq!(natural_cmp("B", "a"), Ordering::Less);
+        assert_eq!(
+            natural_cmp("99999999999999999999999", "100000000000000000000000"),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn sort_paths_test() {
+        let mut paths = vec!["x10".to_string(), "x9".to_string(), "x1".to_string()];
+        sort_paths(&mut paths, SortBy::Name, false);
+        assert_eq!(paths, vec!["x1", "x10", "x9"]);
+        sort_paths(&mut paths, SortBy::Natural, false);
+        assert_eq!(paths, vec!["x1", "x9", "x10"]);
+        sort_paths(&mut paths, SortBy::Natural, true);
+        assert_eq!(paths, vec!["x10", "x9", "x1"]);
+    }
+}
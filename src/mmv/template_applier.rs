@@ -1,21 +1,38 @@
 use itertools::Itertools;
-use regex::Regex;
-use std::fs::DirEntry;
-use std::path::{Path, PathBuf};
-use std::{fs, io};
-
-use crate::mmv::file_utils::{resolve_path_pattern, Template};
-use crate::mmv::{ActionWhenRenamedFilePathExists, TfrError};
-
-pub fn is_rename_template_correct(input_file_template: &str, output_file_template: &str) -> bool {
-    let asterisk_count =
-        input_file_template.matches('*').count() - input_file_template.matches("\\*").count();
-    let placement_regex = Regex::new(r#"#(\d+)"#).unwrap();
-    let correct_unique_flag_count: usize = placement_regex
-        .captures_iter(output_file_template)
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use crate::mmv::auto_rename::{auto_rename_path, is_auto_rename_pattern_correct};
+use crate::mmv::file_utils::{
+    check_path_pattern, find_candidates, is_identical, join_path, placeholders,
+    resolve_path_pattern, PatternError, Template,
+};
+use crate::mmv::sort_by::sort_paths;
+use crate::mmv::{
+    ActionWhenRenamedFilePathExists, ConflictResolution, RenameAction, RenameEntry, RenamePlan,
+    TemplateFileRenamer, TfrError, WhenIdentical,
+};
+
+/// Returns true if every capture is inserted by at least one flag, with or without a modifier. Captures
+/// may be left out if the template has the counter or a metadata placeholder like `#{mtime}`,
+/// `#{exif:Model}` or `#{tag:title}`. Then files are named by the counter or their metadata, destinations
+/// shared by several files are resolved like any other collision
+pub fn is_rename_template_correct(captures_count: usize, output_file_template: &str) -> bool {
+    let has_counter_or_metadata = placeholders(output_file_template)
+        .iter()
+        .any(|placeholder| {
+            placeholder.name("counter").is_some() || placeholder.name("name").is_some()
+        });
+    if has_counter_or_metadata {
+        return true;
+    }
+    let correct_unique_flag_count: usize = placeholders(output_file_template)
+        .iter()
         .filter_map(|capture| {
-            let index = capture.get(1).unwrap().as_str().parse::<usize>().unwrap();
-            if 1 <= index && index <= asterisk_count {
+            let index = capture.name("index")?.as_str().parse::<usize>().unwrap();
+            if 1 <= index && index <= captures_count {
                 Some(index)
             } else {
                 None
@@ -24,81 +41,414 @@ pub fn is_rename_template_correct(input_file_template: &str, output_file_templat
         .unique()
         .count();
 
-    correct_unique_flag_count >= asterisk_count
+    correct_unique_flag_count >= captures_count
+}
+
+/// Decides what to do with entries renamed to the same destination
+///
+/// - `Skip` keeps the first entry, others are skipped
+/// - `Overwrite` keeps the last entry, others are skipped, so colliding source files are never lost
+/// - `OverwriteIfNewer` and `OverwriteIfLarger` keep the newest and the largest entry, others are skipped
+/// - `Terminate` and `Backup` mark all colliding entries as conflicts
+fn resolve_collisions(entries: &mut [RenameEntry], rename_mod: &ActionWhenRenamedFilePathExists) {
+    let collisions = entries
+        .iter()
+        .enumerate()
+        .map(|(idx, entry)| (entry.destination.clone(), idx))
+        .into_group_map();
+
+    for indices in collisions.values().filter(|indices| indices.len() > 1) {
+        let source_metadata = |idx: &usize| fs::metadata(&entries[*idx].source).ok();
+        let kept = match rename_mod {
+            ActionWhenRenamedFilePathExists::Skip => indices.first().copied(),
+            ActionWhenRenamedFilePathExists::Overwrite => indices.last().copied(),
+            ActionWhenRenamedFilePathExists::OverwriteIfNewer => {
+                indices.iter().copied().max_by_key(|idx| {
+                    source_metadata(idx).and_then(|metadata| metadata.modified().ok())
+                })
+            }
+            ActionWhenRenamedFilePathExists::OverwriteIfLarger => indices
+                .iter()
+                .copied()
+                .max_by_key(|idx| source_metadata(idx).map(|metadata| metadata.len())),
+            _ => None,
+        };
+        for &idx in indices {
+            if Some(idx) == kept {
+                continue;
+            }
+            entries[idx].action = match kept {
+                Some(_) => RenameAction::Skip,
+                None => RenameAction::Conflict,
+            };
+            entries[idx].backup = None;
+        }
+    }
+}
+
+/// Marks entries as conflicts if their backup paths are sources or destinations of the batch, moving the
+/// existing destination there would replace a file renamed by the batch
+fn reject_taken_backups(entries: &mut [RenameEntry]) {
+    let batch_paths: HashSet<String> = entries
+        .iter()
+        .flat_map(|entry| [entry.source.clone(), entry.destination.clone()])
+        .collect();
+    for entry in entries.iter_mut() {
+        if entry
+            .backup
+            .as_ref()
+            .is_some_and(|backup| batch_paths.contains(backup))
+        {
+            entry.action = RenameAction::Conflict;
+            entry.backup = None;
+        }
+    }
 }
 
+/// Removes paths nested in other matched directories, they are moved together with the directory
+fn remove_nested(matched_paths: &mut Vec<String>) {
+    let directories: HashSet<String> = matched_paths
+        .iter()
+        .filter(|path| Path::new(path).is_dir())
+        .map(|path| path.trim_end_matches('/').to_string())
+        .collect();
+    matched_paths.retain(|path| {
+        !Path::new(path)
+            .ancestors()
+            .skip(1)
+            .any(|ancestor| directories.contains(ancestor.to_string_lossy().as_ref()))
+    });
+}
+
+/// Replaces the directory moved to an existing directory with its children recursively, so the content
+/// of the directory is merged into the existing one. Merged directories are appended to `merged_directories`
+fn merge_directory(
+    source: String,
+    destination: String,
+    merged_directories: &mut Vec<String>,
+) -> Vec<(String, String)> {
+    let is_merged = Path::new(&source).is_dir()
+        && Path::new(&destination).is_dir()
+        && !Path::new(&destination).starts_with(&source);
+    let children = match fs::read_dir(&source) {
+        Ok(children) if is_merged => children,
+        _ => return vec![(source, destination)],
+    };
+
+    merged_directories.push(source.clone());
+    let mut merged = vec![];
+    for child in children.filter_map(|child| child.ok()) {
+        let name = child.file_name().to_string_lossy().to_string();
+        merged.append(&mut merge_directory(
+            join_path(&source, &name),
+            join_path(&destination, &name),
+            merged_directories,
+        ));
+    }
+    merged
+}
+
+/// Replaces occupied destinations of renamed entries accepted by `is_auto_renamed` with free paths given
+/// by the auto rename pattern
+///
+/// A path is occupied if it exists and is not freed by the batch, or it is the destination of another entry.
+/// Entries keep their destinations in order, so the first entry renamed to a free path keeps it
+fn assign_free_destinations(
+    entries: &mut [RenameEntry],
+    pattern: &str,
+    freed_paths: &HashSet<&str>,
+    is_auto_renamed: impl Fn(&RenameEntry) -> bool,
+) {
+    let planned: HashSet<String> = entries
+        .iter()
+        .map(|entry| entry.destination.clone())
+        .collect();
+    let exists = |path: &str| Path::new(path).exists() && !freed_paths.contains(path);
+
+    let mut assigned: HashSet<String> = HashSet::new();
+    for entry in entries.iter_mut().filter(|entry| entry.is_executed()) {
+        let is_occupied = exists(&entry.destination) || assigned.contains(&entry.destination);
+        if is_occupied && entry.action == RenameAction::Rename && is_auto_renamed(entry) {
+            entry.destination = (1..)
+                .map(|counter| auto_rename_path(&entry.destination, pattern, counter))
+                .find(|candidate| {
+                    !exists(candidate)
+                        && !assigned.contains(candidate)
+                        && !planned.contains(candidate)
+                })
+                .unwrap();
+        }
+        assigned.insert(entry.destination.clone());
+    }
+}
+
+/// Matches paths with the input template and decides what to do with each of them. Entries of the plan
+/// follow the sort order of source paths
+///
+/// Returns Err if templates are incorrect, a capture can not be formatted as a number, metadata of a path
+/// is not available or the conflict handler aborts renaming. Conflicts with existing paths are recorded in
+/// the plan
 pub fn apply_template(
     input_file_template: &str,
     output_file_template: &str,
-    rename_mod: &ActionWhenRenamedFilePathExists,
-) -> Result<Vec<(String, String)>, TfrError> {
-    if !is_rename_template_correct(input_file_template, output_file_template) {
+    tfr: &TemplateFileRenamer,
+) -> Result<RenamePlan, TfrError> {
+    let rename_mod = &tfr.rename_mod;
+    let operation_mode = tfr.operation_mode;
+    let match_kind = tfr.match_kind;
+
+    if input_file_template.is_empty() {
+        return Err(TfrError::IncorrectInputTemplate(
+            "Empty input template does not allowed",
+        ));
+    }
+    let is_auto_renaming = matches!(
+        rename_mod,
+        ActionWhenRenamedFilePathExists::AutoRename | ActionWhenRenamedFilePathExists::Ask
+    );
+    if is_auto_renaming && !is_auto_rename_pattern_correct(&tfr.auto_rename_pattern) {
         return Err(TfrError::IncorrectOutputTemplate(
-            "Output template flags does not cover input template asterisks",
+            "Auto rename pattern must contain the counter #n",
         ));
     }
 
-    let input_dir =
-        Path::new(input_file_template)
-            .parent()
-            .ok_or(TfrError::IncorrectInputTemplate(
-                "Empty input template does not allowed",
-            ))?;
-    let input_dir = fs::read_dir(input_dir).map_err(|_| {
-        TfrError::IncorrectInputTemplate("Input template parent directory not found")
-    })?;
-
     let input_file_template = Template::new(input_file_template)?;
 
-    let extract_file_path = |path: DirEntry| {
-        if path.file_type().unwrap().is_file() {
-            return Some(path.path());
-        }
-        None
-    };
-    let file_candidates = input_dir
-        .filter_map(|entry: io::Result<DirEntry>| entry.ok().and_then(extract_file_path))
-        .collect::<Vec<PathBuf>>();
+    if !is_rename_template_correct(input_file_template.captures_count(), output_file_template) {
+        return Err(TfrError::IncorrectOutputTemplate(
+            "Output template flags does not cover input template asterisks",
+        ));
+    }
+    check_path_pattern(output_file_template).map_err(TfrError::IncorrectOutputTemplate)?;
 
-    let mut existing_path: Option<String> = None;
+    let file_candidates = find_candidates(
+        input_file_template.search_root(),
+        input_file_template.search_depth(),
+        match_kind,
+    )
+    .map_err(|_| TfrError::IncorrectInputTemplate("Input template parent directory not found"))?;
 
-    let mut apply_template_to_filepath =
-        |input_path: &str, captures: Vec<&str>| -> Option<(String, String)> {
-            let new_filepath = resolve_path_pattern(output_file_template, captures);
-            if !Path::new(&new_filepath).exists() {
-                return Some((input_path.to_string(), new_filepath));
+    // The conflict handler is called once for every source, the same conflict is decided again while
+    // freed paths are shrinking
+    let decisions: RefCell<HashMap<String, ConflictResolution>> = RefCell::new(HashMap::new());
+    let resolution_for_all: Cell<Option<ConflictResolution>> = Cell::new(None);
+    let ask = |input_path: &str, new_filepath: &str| -> Option<ConflictResolution> {
+        if let Some(resolution) = decisions.borrow().get(input_path) {
+            return Some(*resolution);
+        }
+        let resolution = match resolution_for_all.get() {
+            Some(resolution) => resolution,
+            None => {
+                let decision = (tfr.conflict_handler.as_ref()?)(input_path, new_filepath);
+                if decision.apply_to_all {
+                    resolution_for_all.set(Some(decision.resolution));
+                }
+                decision.resolution
             }
-            if Path::new(&new_filepath).is_dir() {
-                existing_path = Some(new_filepath.to_string());
-                return None;
+        };
+        decisions
+            .borrow_mut()
+            .insert(input_path.to_string(), resolution);
+        Some(resolution)
+    };
+
+    let decide_action = |input_path: &str,
+                         new_filepath: &str|
+     -> Result<(RenameAction, Option<String>), TfrError> {
+        let is_directory = Path::new(input_path).is_dir();
+        if is_directory && Path::new(new_filepath).starts_with(input_path) {
+            // Directory can not be moved into itself
+            return Ok((RenameAction::Conflict, None));
+        }
+        if !Path::new(new_filepath).exists() {
+            return Ok((RenameAction::Rename, None));
+        }
+        if is_directory || Path::new(new_filepath).is_dir() {
+            return Ok(match (is_directory, rename_mod) {
+                (true, ActionWhenRenamedFilePathExists::Skip) => (RenameAction::Skip, None),
+                (_, ActionWhenRenamedFilePathExists::AutoRename) => (RenameAction::Rename, None),
+                _ => (RenameAction::Conflict, None),
+            });
+        }
+        let is_identical = || is_identical(input_path, new_filepath).unwrap_or(false);
+        match tfr.when_identical {
+            WhenIdentical::Skip if is_identical() => return Ok((RenameAction::Skip, None)),
+            WhenIdentical::RemoveSource if is_identical() => {
+                return Ok(match operation_mode.moves_sources() {
+                    true => (RenameAction::RemoveSource, None),
+                    false => (RenameAction::Skip, None),
+                })
             }
-            match rename_mod {
-                ActionWhenRenamedFilePathExists::Terminate => {
-                    existing_path = Some(new_filepath.to_string());
-                    None
-                }
-                ActionWhenRenamedFilePathExists::Skip => None,
-                ActionWhenRenamedFilePathExists::Overwrite => {
-                    Some((input_path.to_string(), new_filepath))
+            _ => {}
+        }
+        let metadata = |path: &str| fs::metadata(path).ok();
+        let overwrite_if = |condition: bool| match condition {
+            true => (RenameAction::Overwrite, None),
+            false => (RenameAction::Skip, None),
+        };
+        Ok(match rename_mod {
+            ActionWhenRenamedFilePathExists::Terminate => (RenameAction::Conflict, None),
+            ActionWhenRenamedFilePathExists::Skip => (RenameAction::Skip, None),
+            ActionWhenRenamedFilePathExists::Overwrite => (RenameAction::Overwrite, None),
+            ActionWhenRenamedFilePathExists::Backup => {
+                let backup = tfr.backup_scheme.backup_path(new_filepath);
+                match Path::new(&backup).is_dir() {
+                    true => (RenameAction::Conflict, None),
+                    false => (RenameAction::Backup, Some(backup)),
                 }
             }
-        };
+            // Occupied destination is replaced with a free one after all entries are matched
+            ActionWhenRenamedFilePathExists::AutoRename => (RenameAction::Rename, None),
+            ActionWhenRenamedFilePathExists::OverwriteIfNewer => {
+                let modified = |path| metadata(path)?.modified().ok();
+                overwrite_if(modified(input_path) > modified(new_filepath))
+            }
+            ActionWhenRenamedFilePathExists::OverwriteIfLarger => {
+                let size = |path| metadata(path).map(|metadata| metadata.len());
+                overwrite_if(size(input_path) > size(new_filepath))
+            }
+            ActionWhenRenamedFilePathExists::Ask => match ask(input_path, new_filepath) {
+                None => (RenameAction::Conflict, None),
+                Some(ConflictResolution::Overwrite) => (RenameAction::Overwrite, None),
+                Some(ConflictResolution::Skip) => (RenameAction::Skip, None),
+                // Occupied destination is replaced with a free one after all entries are matched
+                Some(ConflictResolution::Rename) => (RenameAction::Rename, None),
+                Some(ConflictResolution::Abort) => return Err(TfrError::Aborted),
+            },
+        })
+    };
 
-    let applied_new_filepaths: Vec<(String, String)> = file_candidates
-        .iter()
-        .filter_map(|input_path: &PathBuf| {
-            let input_path = input_path.to_str().unwrap().to_string();
-            input_file_template
+    let mut matched_paths: Vec<String> = file_candidates
+        .into_iter()
+        .filter(|input_path| input_file_template.captures(input_path).is_some())
+        .collect();
+    sort_paths(&mut matched_paths, tfr.sort_by, tfr.reverse_order);
+    if match_kind.matches_directories() {
+        remove_nested(&mut matched_paths);
+    }
+
+    // Counters are enumerated in the order of matched paths
+    let mut matched_filepaths: Vec<(String, String)> = matched_paths
+        .into_iter()
+        .enumerate()
+        .map(|(index, input_path)| {
+            let captures = input_file_template
                 .captures(&input_path)
-                .and_then(|captures| apply_template_to_filepath(&input_path, captures))
+                .unwrap_or_default();
+            let resolved = resolve_path_pattern(
+                output_file_template,
+                &input_path,
+                captures,
+                index,
+                &tfr.metadata_fallback,
+            );
+            match resolved {
+                Ok(new_filepath) => Ok((input_path, new_filepath)),
+                // The file is renamed to itself, so it is skipped
+                Err(PatternError::MissingTag(_)) => Ok((input_path.clone(), input_path)),
+                Err(PatternError::NotNumericCapture(capture)) => {
+                    Err(TfrError::NotNumericCapture(input_path, capture))
+                }
+                Err(PatternError::MissingMetadata(placeholder)) => {
+                    Err(TfrError::MissingMetadata(input_path, placeholder))
+                }
+            }
         })
-        .collect();
+        .collect::<Result<_, TfrError>>()?;
+    let mut merged_directories = vec![];
+    if tfr.merge_directories {
+        matched_filepaths = matched_filepaths
+            .into_iter()
+            .flat_map(|(input_path, new_filepath)| {
+                merge_directory(input_path, new_filepath, &mut merged_directories)
+            })
+            .collect();
+    }
+
+    // Existing destination is free if its file is moved in the same batch. Freed paths are shrinking
+    // while some sources are not moved because of conflicts
+    let mut freed_paths: HashSet<&str> = match operation_mode.moves_sources() {
+        true => matched_filepaths
+            .iter()
+            .map(|(input_path, _)| input_path.as_str())
+            .collect(),
+        false => HashSet::new(),
+    };
+    loop {
+        let mut entries: Vec<RenameEntry> = matched_filepaths
+            .iter()
+            .map(|(input_path, new_filepath)| {
+                let (action, backup) = if input_path == new_filepath {
+                    (RenameAction::Skip, None)
+                } else if freed_paths.contains(new_filepath.as_str()) {
+                    (RenameAction::Rename, None)
+                } else {
+                    decide_action(input_path, new_filepath)?
+                };
+                Ok(RenameEntry {
+                    source: input_path.to_string(),
+                    destination: new_filepath.clone(),
+                    action,
+                    backup,
+                })
+            })
+            .collect::<Result<_, TfrError>>()?;
 
-    match existing_path {
-        None => Ok(applied_new_filepaths),
-        Some(existing_path) => Err(TfrError::ExistingPath(
-            existing_path.clone(),
-            Path::new(&existing_path).is_file(),
-        )),
+        match rename_mod {
+            ActionWhenRenamedFilePathExists::AutoRename => assign_free_destinations(
+                &mut entries,
+                &tfr.auto_rename_pattern,
+                &freed_paths,
+                |_| true,
+            ),
+            ActionWhenRenamedFilePathExists::Ask => {
+                let decisions = decisions.borrow();
+                assign_free_destinations(
+                    &mut entries,
+                    &tfr.auto_rename_pattern,
+                    &freed_paths,
+                    |entry| decisions.get(&entry.source) == Some(&ConflictResolution::Rename),
+                )
+            }
+            _ => {}
+        }
+        reject_taken_backups(&mut entries);
+        resolve_collisions(&mut entries, rename_mod);
+
+        let moved_paths: HashSet<&str> = matched_filepaths
+            .iter()
+            .zip(&entries)
+            .filter(|(_, entry)| entry.frees_source())
+            .map(|((input_path, _), _)| input_path.as_str())
+            .filter(|input_path| freed_paths.contains(input_path))
+            .collect();
+        if moved_paths.len() == freed_paths.len() {
+            let mut plan = RenamePlan::new(entries, operation_mode);
+            plan.set_merged_directories(merged_directories);
+            return Ok(plan);
+        }
+        freed_paths = moved_paths;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_rename_template_correct_test() {
+        assert!(is_rename_template_correct(2, "out/#2_#1.txt"));
+        assert!(!is_rename_template_correct(2, "out/#1.txt"));
+        assert!(!is_rename_template_correct(1, "out/#name.txt"));
+        assert!(is_rename_template_correct(1, "out/#n.txt"));
+        assert!(is_rename_template_correct(1, "out/#{mtime:%Y}.txt"));
+        assert!(is_rename_template_correct(
+            1,
+            "out/#{exif:DateTime:%Y}_#{exif:Model}.jpg"
+        ));
+        assert!(is_rename_template_correct(
+            1,
+            "out/#{tag:artist}_#{tag:title}.mp3"
+        ));
     }
 }
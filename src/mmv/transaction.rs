@@ -0,0 +1,192 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::mmv::file_utils::{join_path, move_file};
+use crate::mmv::rename_order::temporary_path;
+
+/// Filesystem change made while executing the plan
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Operation {
+    Renamed {
+        from: String,
+        to: String,
+    },
+    CreatedFile(String),
+    CreatedDirectory(String),
+    RemovedDirectory(String),
+    /// File removed without keeping its content, it can not be restored
+    DeletedFile(String),
+}
+
+/// Records every filesystem change made while executing the plan, so they can be reverted.
+///
+/// If the transaction is reversible, removed files are moved aside to temporary paths and deleted or saved only
+/// on [commit](Transaction::commit)
+///
+/// Files are moved between filesystems by copying, `on_copy` is called before each copy
+pub struct Transaction<'a> {
+    reversible: bool,
+    operations: Vec<Operation>,
+    discarded: Vec<String>,
+    on_copy: &'a dyn Fn(&str, &str),
+}
+
+impl<'a> Transaction<'a> {
+    pub fn new(reversible: bool, on_copy: &'a dyn Fn(&str, &str)) -> Self {
+        Self {
+            reversible,
+            operations: vec![],
+            discarded: vec![],
+            on_copy,
+        }
+    }
+
+    pub fn rename(&mut self, from: &str, to: &str) -> io::Result<()> {
+        move_file(from, to, || (self.on_copy)(from, to))?;
+        self.operations.push(Operation::Renamed {
+            from: from.to_string(),
+            to: to.to_string(),
+        });
+        Ok(())
+    }
+
+    /// Copies the file, the copy is removed on rollback
+    pub fn copy(&mut self, from: &str, to: &str) -> io::Result<()> {
+        fs::copy(from, to)?;
+        self.operations.push(Operation::CreatedFile(to.to_string()));
+        Ok(())
+    }
+
+    /// Creates a hard link to the file, the link is removed on rollback
+    pub fn hard_link(&mut self, from: &str, to: &str) -> io::Result<()> {
+        fs::hard_link(from, to)?;
+        self.operations.push(Operation::CreatedFile(to.to_string()));
+        Ok(())
+    }
+
+    /// Creates a symbolic link to `target`, the link is removed on rollback
+    pub fn symlink(&mut self, target: &str, to: &str) -> io::Result<()> {
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(target, to)?;
+        #[cfg(windows)]
+        match Path::new(to)
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(target)
+            .is_dir()
+        {
+            true => std::os::windows::fs::symlink_dir(target, to)?,
+            false => std::os::windows::fs::symlink_file(target, to)?,
+        }
+        self.operations.push(Operation::CreatedFile(to.to_string()));
+        Ok(())
+    }
+
+    /// Recreates the directory tree at `to`, files are copied or hard linked if `hard_link` is set.
+    /// Every created path is removed on rollback
+    pub fn copy_directory(&mut self, from: &str, to: &str, hard_link: bool) -> io::Result<()> {
+        fs::create_dir(to)?;
+        self.operations
+            .push(Operation::CreatedDirectory(to.to_string()));
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            let (from, to) = (join_path(from, &name), join_path(to, &name));
+            match (entry.file_type()?.is_dir(), hard_link) {
+                (true, _) => self.copy_directory(&from, &to, hard_link)?,
+                (false, true) => self.hard_link(&from, &to)?,
+                (false, false) => self.copy(&from, &to)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates all missing parent directories of the path one by one
+    pub fn create_parent_directories(&mut self, path: &str) -> io::Result<()> {
+        let mut missing: Vec<&Path> = Path::new(path)
+            .ancestors()
+            .skip(1)
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .take_while(|ancestor| !ancestor.exists())
+            .collect();
+        missing.reverse();
+
+        for directory in missing {
+            fs::create_dir(directory)?;
+            self.operations.push(Operation::CreatedDirectory(
+                directory.to_string_lossy().to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Removes the empty directory, it is created again on rollback
+    pub fn remove_directory(&mut self, path: &str) -> io::Result<()> {
+        fs::remove_dir(path)?;
+        self.operations
+            .push(Operation::RemovedDirectory(path.to_string()));
+        Ok(())
+    }
+
+    pub fn remove_file(&mut self, path: &str) -> io::Result<()> {
+        if !self.reversible {
+            fs::remove_file(path)?;
+            self.operations
+                .push(Operation::DeletedFile(path.to_string()));
+            return Ok(());
+        }
+        let discarded = temporary_path(path, &|candidate| !Path::new(candidate).exists());
+        self.rename(path, &discarded)?;
+        self.discarded.push(discarded);
+        Ok(())
+    }
+
+    /// Deletes files removed during the transaction or moves them to `saved_directory`, if it is set
+    ///
+    /// Returns all performed operations, moves of deleted files aside are replaced with
+    /// [DeletedFile](Operation::DeletedFile)
+    pub fn commit(mut self, saved_directory: Option<&Path>) -> io::Result<Vec<Operation>> {
+        let Some(saved_directory) = saved_directory else {
+            for discarded in &self.discarded {
+                fs::remove_file(discarded)?;
+            }
+            let operations = self
+                .operations
+                .into_iter()
+                .map(|operation| match operation {
+                    Operation::Renamed { from, to } if self.discarded.contains(&to) => {
+                        Operation::DeletedFile(from)
+                    }
+                    operation => operation,
+                });
+            return Ok(operations.collect());
+        };
+
+        if !self.discarded.is_empty() {
+            fs::create_dir_all(saved_directory)?;
+        }
+        for (idx, discarded) in std::mem::take(&mut self.discarded).iter().enumerate() {
+            let file_name = Path::new(discarded).file_name().unwrap_or_default();
+            let saved = saved_directory.join(format!("{idx}-{}", file_name.to_string_lossy()));
+            self.rename(discarded, &saved.to_string_lossy())?;
+        }
+        Ok(self.operations)
+    }
+
+    /// Reverts all recorded operations in reverse order. Returns errors of operations that could not be reverted
+    pub fn rollback(self) -> Vec<io::Error> {
+        self.operations
+            .iter()
+            .rev()
+            .filter_map(|operation| match operation {
+                Operation::Renamed { from, to } => move_file(to, from, || {}).err(),
+                Operation::CreatedFile(file) => fs::remove_file(file).err(),
+                Operation::CreatedDirectory(directory) => fs::remove_dir(directory).err(),
+                Operation::RemovedDirectory(directory) => fs::create_dir(directory).err(),
+                Operation::DeletedFile(_) => None,
+            })
+            .collect()
+    }
+}
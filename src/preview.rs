@@ -0,0 +1,118 @@
+use tfr::{RenameAction, RenameEntry, RenamePlan};
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+/// Splits both strings into common prefix, differing middle and common suffix
+///
+/// Returns byte ranges of the middles: (old_start, old_end, new_start, new_end)
+fn difference_bounds(old: &str, new: &str) -> (usize, usize, usize, usize) {
+    let prefix: usize = old
+        .chars()
+        .zip(new.chars())
+        .take_while(|(old_char, new_char)| old_char == new_char)
+        .map(|(old_char, _)| old_char.len_utf8())
+        .sum();
+    let suffix: usize = old[prefix..]
+        .chars()
+        .rev()
+        .zip(new[prefix..].chars().rev())
+        .take_while(|(old_char, new_char)| old_char == new_char)
+        .map(|(old_char, _)| old_char.len_utf8())
+        .sum();
+
+    (prefix, old.len() - suffix, prefix, new.len() - suffix)
+}
+
+fn highlight(path: &str, start: usize, end: usize, color: &str) -> String {
+    format!(
+        "{}{color}{}{RESET}{}",
+        &path[..start],
+        &path[start..end],
+        &path[end..]
+    )
+}
+
+fn action_note(plan: &RenamePlan, entry: &RenameEntry) -> String {
+    match (entry.action, &entry.backup) {
+        (RenameAction::Conflict, _) if plan.sources_of(&entry.destination).len() > 1 => {
+            " (collision)".to_string()
+        }
+        (RenameAction::Rename, _) => String::new(),
+        (RenameAction::Overwrite, _) => " (overwrite)".to_string(),
+        (RenameAction::Backup, Some(backup)) => format!(" (backup to {backup})"),
+        (RenameAction::Backup, None) => " (backup)".to_string(),
+        (RenameAction::Skip, _) => " (skip)".to_string(),
+        (RenameAction::Conflict, _) => " (conflict)".to_string(),
+        (RenameAction::RemoveSource, _) => " (identical, remove)".to_string(),
+    }
+}
+
+/// Prints every entry of the plan as `old -> new` followed by the summary.
+///
+/// If `colored` is set, changed parts of paths and not renamed entries are highlighted
+pub fn print_plan(plan: &RenamePlan, colored: bool) {
+    for entry in plan.entries() {
+        let note = action_note(plan, entry);
+        if !colored {
+            println!("{} -> {}{note}", entry.source, entry.destination);
+            continue;
+        }
+
+        let (old_start, old_end, new_start, new_end) =
+            difference_bounds(&entry.source, &entry.destination);
+        let note_color = match entry.action {
+            RenameAction::Conflict => RED,
+            _ => YELLOW,
+        };
+        println!(
+            "{} -> {}{note_color}{note}{RESET}",
+            highlight(&entry.source, old_start, old_end, RED),
+            highlight(&entry.destination, new_start, new_end, GREEN),
+        );
+    }
+
+    let count = |action: RenameAction| {
+        plan.entries()
+            .iter()
+            .filter(|entry| entry.action == action)
+            .count()
+    };
+    println!(
+        "Dry run. Files to rename: {}, overwritten: {}, backed up: {}, skipped: {}, removed: {}, conflicting: {}",
+        plan.executed_entries().count(),
+        count(RenameAction::Overwrite),
+        count(RenameAction::Backup),
+        count(RenameAction::Skip),
+        count(RenameAction::RemoveSource),
+        count(RenameAction::Conflict),
+    );
+    if !plan.created_directories().is_empty() {
+        println!(
+            "Directories to create: {}",
+            plan.created_directories().join(", ")
+        );
+    }
+    if !plan.merged_directories().is_empty() {
+        println!(
+            "Directories merged and removed if emptied: {}",
+            plan.merged_directories().join(", ")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn difference_bounds_test() {
+        assert_eq!(difference_bounds("a.txt", "a.txt"), (5, 5, 5, 5));
+        assert_eq!(difference_bounds("dir/a.txt", "dir/b.txt"), (4, 5, 4, 5));
+        assert_eq!(difference_bounds("a.png", "x/a.png"), (0, 0, 0, 2));
+        assert_eq!(difference_bounds("a_b.JPG", "b_a.jpg"), (0, 7, 0, 7));
+        assert_eq!(difference_bounds("ä.txt", "äö.txt"), (2, 2, 2, 4));
+    }
+}
@@ -0,0 +1,346 @@
+mod files_environment;
+
+use crate::files_environment::{
+    ActionWhenExists, FilesEnvironment, FilesEnvironmentConfig, Match, Mode, Sort,
+};
+use std::io;
+use std::io::Read;
+use tfr::BackupScheme;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+mod cli_tests {
+    use super::*;
+
+    fn read_environment_config(config_path: &str) -> Result<FilesEnvironmentConfig, io::Error> {
+        let mut file = std::fs::File::open(config_path)?;
+        let mut data = String::new();
+        file.read_to_string(&mut data)?;
+        Ok(serde_json::from_str::<FilesEnvironmentConfig>(&data)
+            .expect("JSON was not well-formatted"))
+    }
+
+    fn run_tfr(args: &[&str], state_dir: &str) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_tfr"))
+            .args(args)
+            .env("TFR_STATE_DIR", state_dir)
+            .output()
+            .expect("tfr binary was not started")
+    }
+
+    fn action_flag(environment_config: &FilesEnvironmentConfig) -> Option<String> {
+        let flag = match environment_config.action_when_exists {
+            ActionWhenExists::Terminate => return None,
+            ActionWhenExists::Skip => "--skip",
+            ActionWhenExists::Overwrite => "--force",
+            ActionWhenExists::Backup => match environment_config.backup_scheme() {
+                BackupScheme::Simple { .. } => "--backup",
+                BackupScheme::Numbered => "--backup=numbered",
+            },
+            ActionWhenExists::Ask => "--interactive",
+            ActionWhenExists::AutoRename => match &environment_config.auto_rename_pattern {
+                None => "--auto-rename",
+                Some(pattern) => return Some(format!("--auto-rename={pattern}")),
+            },
+        };
+        Some(flag.to_string())
+    }
+
+    /// Runs the binary on the environment of the config, `environment_name` is suffixed to not interfere with
+    /// library tests running in parallel
+    fn test_with_json_config(config_path: &str) {
+        let config_path = format!("tests/tests/{}", config_path);
+        let mut environment_config = read_environment_config(&config_path).unwrap();
+        environment_config.environment_name += "_cli";
+        let files_environment = FilesEnvironment::new(&environment_config).unwrap();
+        run_with_environment(&environment_config, &files_environment);
+    }
+
+    fn run_with_environment(
+        environment_config: &FilesEnvironmentConfig,
+        files_environment: &FilesEnvironment,
+    ) {
+        let input_template = files_environment.get_full_path(&environment_config.input_template);
+        let output_template = files_environment.get_full_path(&environment_config.output_template);
+        let mut args = vec![input_template.as_str(), output_template.as_str()];
+        let action_flag = action_flag(environment_config);
+        args.extend(action_flag.as_deref());
+        if environment_config.transactional {
+            args.push("--transactional");
+        }
+        args.extend(match environment_config.mode {
+            Mode::Move => vec![],
+            Mode::Copy => vec!["--copy"],
+            Mode::HardLink => vec!["--hardlink"],
+            Mode::SymLink => vec!["--symlink"],
+            Mode::RelativeSymLink => vec!["--symlink", "--relative"],
+        });
+        args.extend(match environment_config.match_kind {
+            Match::Files => None,
+            Match::Directories => Some("--dirs"),
+            Match::All => Some("--all"),
+        });
+        let suffix;
+        if let BackupScheme::Simple { suffix: backup_suffix } = environment_config.backup_scheme() {
+            suffix = format!("--suffix={backup_suffix}");
+            args.push(&suffix);
+        }
+        if environment_config.merge_directories {
+            args.push("--merge");
+        }
+        args.push(match environment_config.sort {
+            Sort::Name => "--sort=name",
+            Sort::Natural => "--sort=natural",
+            Sort::Mtime => "--sort=mtime",
+            Sort::Btime => "--sort=btime",
+            Sort::Ctime => "--sort=ctime",
+            Sort::Size => "--sort=size",
+        });
+        if environment_config.reverse {
+            args.push("--reverse");
+        }
+
+        let output = run_tfr(&args, &files_environment.get_full_path("state"));
+        assert_eq!(
+            output.status.success(),
+            !environment_config.raise_error,
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert!(files_environment.is_after())
+    }
+
+    #[test]
+    fn simple_test() {
+        test_with_json_config("simple.json");
+    }
+
+    #[test]
+    fn terminate_test() {
+        test_with_json_config("terminate.json");
+    }
+
+    #[test]
+    fn skip_when_exists_test() {
+        test_with_json_config("skip_when_exists.json");
+    }
+
+    #[test]
+    fn overwrite_when_exists_test() {
+        test_with_json_config("overwrite_when_exists.json");
+    }
+
+    #[test]
+    fn backup_when_exists_test() {
+        test_with_json_config("backup_when_exists.json");
+    }
+
+    #[test]
+    fn backup_taken_by_batch_test() {
+        test_with_json_config("backup_taken.json");
+        test_with_json_config("backup_taken_reverse.json");
+    }
+
+    #[test]
+    fn backup_scheme_test() {
+        test_with_json_config("backup_suffix.json");
+        test_with_json_config("numbered_backup.json");
+    }
+
+    #[test]
+    fn auto_rename_test() {
+        test_with_json_config("auto_rename.json");
+        test_with_json_config("auto_rename_pattern.json");
+    }
+
+    #[test]
+    fn interactive_test() {
+        let mut environment_config = read_environment_config("tests/tests/ask.json").unwrap();
+        environment_config.environment_name += "_cli";
+        let files_environment = FilesEnvironment::new(&environment_config).unwrap();
+        let input_template = files_environment.get_full_path(&environment_config.input_template);
+        let output_template = files_environment.get_full_path(&environment_config.output_template);
+
+        // Closed input aborts renaming
+        let state_dir = files_environment.get_full_path("state");
+        let output = run_tfr(&["-i", &input_template, &output_template], &state_dir);
+        assert!(!output.status.success());
+        assert!(files_environment.is_before());
+
+        let mut child = Command::new(env!("CARGO_BIN_EXE_tfr"))
+            .args(["-i", &input_template, &output_template])
+            .env("TFR_STATE_DIR", &state_dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("tfr binary was not started");
+        // Unknown answer is asked again
+        child.stdin.take().unwrap().write_all(b"o\nx\ns\nr\n").unwrap();
+        assert!(child.wait().unwrap().success());
+        assert!(files_environment.is_after());
+    }
+
+    #[test]
+    fn case_modifiers_test() {
+        test_with_json_config("case_modifiers.json");
+    }
+
+    #[test]
+    fn counter_test() {
+        test_with_json_config("counter.json");
+    }
+
+    #[test]
+    fn sort_test() {
+        test_with_json_config("natural_sort.json");
+        test_with_json_config("size_sort.json");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ctime_sort_test() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut environment_config =
+            read_environment_config("tests/tests/ctime_sort.json").unwrap();
+        environment_config.environment_name += "_cli";
+        let files_environment = FilesEnvironment::new(&environment_config).unwrap();
+        // Inode change times go in the reverse order of names and modification times
+        for path in ["in/c.txt", "in/b.txt", "in/a.txt"] {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            let permissions = std::fs::Permissions::from_mode(0o640);
+            let path = files_environment.get_full_path(path);
+            std::fs::set_permissions(path, permissions).unwrap();
+        }
+        run_with_environment(&environment_config, &files_environment);
+    }
+
+    #[test]
+    fn number_format_test() {
+        test_with_json_config("number_format.json");
+        test_with_json_config("not_numeric_capture.json");
+    }
+
+    #[test]
+    fn copy_test() {
+        test_with_json_config("copy.json");
+    }
+
+    #[test]
+    fn link_test() {
+        test_with_json_config("hard_link.json");
+        test_with_json_config("symlink.json");
+        test_with_json_config("relative_symlink.json");
+    }
+
+    #[test]
+    fn match_directories_test() {
+        test_with_json_config("directory.json");
+        test_with_json_config("directory_all.json");
+        test_with_json_config("nested_directories.json");
+        test_with_json_config("merge_directories.json");
+    }
+
+    #[test]
+    fn rollback_test() {
+        test_with_json_config("rollback.json");
+    }
+
+    #[test]
+    fn undo_test() {
+        let mut environment_config = read_environment_config("tests/tests/undo.json").unwrap();
+        environment_config.environment_name += "_cli";
+        let files_environment = FilesEnvironment::new(&environment_config).unwrap();
+        let full_path = |path: &str| files_environment.get_full_path(path);
+        let state_dir = full_path("state");
+
+        let input_template = full_path(&environment_config.input_template);
+        let output_template = full_path(&environment_config.output_template);
+        let args = [&input_template, &output_template, "--force", "--keep-overwritten"];
+        assert!(run_tfr(&args, &state_dir).status.success());
+        assert!(files_environment.is_after());
+        assert!(run_tfr(&["undo"], &state_dir).status.success());
+        assert!(files_environment.is_before());
+        assert!(!run_tfr(&["undo"], &state_dir).status.success());
+
+        run_with_environment(&environment_config, &files_environment);
+        assert!(!run_tfr(&["undo", "unknown-batch"], &state_dir).status.success());
+        assert!(files_environment.is_after());
+        let output = run_tfr(&["undo"], &state_dir);
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains(&format!("{} was deleted", full_path("old/after.txt"))));
+        assert!(!std::path::Path::new(&full_path("old/after.txt")).exists());
+    }
+
+    #[test]
+    fn exif_time_zone_test() {
+        let mut environment_config = read_environment_config("tests/tests/exif.json").unwrap();
+        environment_config.environment_name += "_cli";
+        let files_environment = FilesEnvironment::new(&environment_config).unwrap();
+        let full_path = |path: &str| files_environment.get_full_path(path);
+        let state_dir = full_path("state");
+
+        let input_template = full_path(&environment_config.input_template);
+        for format in ["%Y%z", "%Z"] {
+            let output_template = full_path(&format!("out/#{{exif:DateTime:{format}}}_#1.jpg"));
+            let output = run_tfr(&[&input_template, &output_template], &state_dir);
+            assert_eq!(output.status.code(), Some(1));
+        }
+        assert!(files_environment.is_before());
+    }
+
+    #[test]
+    fn no_journal_test() {
+        let mut environment_config = read_environment_config("tests/tests/undo.json").unwrap();
+        environment_config.environment_name += "_no_journal";
+        let files_environment = FilesEnvironment::new(&environment_config).unwrap();
+        let full_path = |path: &str| files_environment.get_full_path(path);
+        let state_dir = full_path("state");
+
+        let input_template = full_path(&environment_config.input_template);
+        let output_template = full_path(&environment_config.output_template);
+        let args = [&input_template, &output_template, "--force", "--no-journal"];
+        assert!(run_tfr(&args, &state_dir).status.success());
+        assert!(files_environment.is_after());
+        assert!(!std::path::Path::new(&state_dir).exists());
+        assert!(!run_tfr(&["undo"], &state_dir).status.success());
+
+        let args = [&input_template, &output_template, "--no-journal", "--keep-overwritten"];
+        assert_eq!(run_tfr(&args, &state_dir).status.code(), Some(2));
+    }
+
+    #[test]
+    fn dry_run_test() {
+        let mut environment_config = read_environment_config("tests/tests/plan.json").unwrap();
+        environment_config.environment_name += "_dry_run";
+        let files_environment = FilesEnvironment::new(&environment_config).unwrap();
+        let full_path = |path: &str| files_environment.get_full_path(path);
+
+        let input_template = full_path(&environment_config.input_template);
+        let output_template = full_path(&environment_config.output_template);
+        for flag in ["-n", "--dry-run"] {
+            let args = [input_template.as_str(), &output_template, flag];
+            let output = run_tfr(&args, &full_path("state"));
+            let stdout = String::from_utf8_lossy(&output.stdout);
+
+            assert_eq!(output.status.code(), Some(1));
+            assert!(files_environment.is_before());
+            assert!(!std::path::Path::new(&full_path("out/a")).exists());
+            let renamed = format!("{} -> {}", full_path("in/a.txt"), full_path("out/a/file.txt"));
+            assert!(stdout.contains(&renamed));
+            let summary = "Dry run. Files to rename: 2, overwritten: 0, backed up: 0, skipped: 0, \
+                removed: 0, conflicting: 1";
+            assert!(stdout.contains(summary));
+        }
+    }
+
+    #[test]
+    fn conflicting_flags_test() {
+        for flags in [["--force", "--skip"], ["--skip", "--backup"], ["-f", "-b"]] {
+            let output = run_tfr(&["from_*.txt", "to_#1.txt", flags[0], flags[1]], "state");
+            assert_eq!(output.status.code(), Some(2));
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use tfr::ActionWhenRenamedFilePathExists;
+use tfr::{ActionWhenRenamedFilePathExists, BackupScheme, MatchKind, OperationMode, SortBy};
 use serde::Deserialize;
 use std::collections::BTreeSet;
 use std::fs::{create_dir_all, read_to_string, remove_dir_all};
@@ -12,14 +12,87 @@ pub enum ActionWhenExists {
     Terminate,
     Skip,
     Overwrite,
+    Backup,
+    AutoRename,
+    Ask,
 }
 
-impl Into<ActionWhenRenamedFilePathExists> for ActionWhenExists {
-    fn into(self) -> ActionWhenRenamedFilePathExists {
-        match self {
+impl From<ActionWhenExists> for ActionWhenRenamedFilePathExists {
+    fn from(action_when_exists: ActionWhenExists) -> Self {
+        match action_when_exists {
             ActionWhenExists::Terminate => ActionWhenRenamedFilePathExists::Terminate,
             ActionWhenExists::Skip => ActionWhenRenamedFilePathExists::Skip,
             ActionWhenExists::Overwrite => ActionWhenRenamedFilePathExists::Overwrite,
+            ActionWhenExists::Backup => ActionWhenRenamedFilePathExists::Backup,
+            ActionWhenExists::AutoRename => ActionWhenRenamedFilePathExists::AutoRename,
+            ActionWhenExists::Ask => ActionWhenRenamedFilePathExists::Ask,
+        }
+    }
+}
+
+#[derive(Default, Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    #[default]
+    Move,
+    Copy,
+    HardLink,
+    SymLink,
+    RelativeSymLink,
+}
+
+impl From<Mode> for OperationMode {
+    fn from(mode: Mode) -> Self {
+        match mode {
+            Mode::Move => OperationMode::Move,
+            Mode::Copy => OperationMode::Copy,
+            Mode::HardLink => OperationMode::HardLink,
+            Mode::SymLink => OperationMode::SymLink { relative: false },
+            Mode::RelativeSymLink => OperationMode::SymLink { relative: true },
+        }
+    }
+}
+
+#[derive(Default, Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Match {
+    #[default]
+    Files,
+    Directories,
+    All,
+}
+
+impl From<Match> for MatchKind {
+    fn from(match_kind: Match) -> Self {
+        match match_kind {
+            Match::Files => MatchKind::Files,
+            Match::Directories => MatchKind::Directories,
+            Match::All => MatchKind::All,
+        }
+    }
+}
+
+#[derive(Default, Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Sort {
+    Name,
+    #[default]
+    Natural,
+    Mtime,
+    Btime,
+    Ctime,
+    Size,
+}
+
+impl From<Sort> for SortBy {
+    fn from(sort: Sort) -> Self {
+        match sort {
+            Sort::Name => SortBy::Name,
+            Sort::Natural => SortBy::Natural,
+            Sort::Mtime => SortBy::Modified,
+            Sort::Btime => SortBy::Created,
+            Sort::Ctime => SortBy::Changed,
+            Sort::Size => SortBy::Size,
         }
     }
 }
@@ -37,6 +110,36 @@ pub struct FilesEnvironmentConfig {
     pub raise_error: bool,
     #[serde(default)]
     pub action_when_exists: ActionWhenExists,
+    #[serde(default)]
+    pub transactional: bool,
+    #[serde(default)]
+    pub mode: Mode,
+    #[serde(default, rename = "match")]
+    pub match_kind: Match,
+    #[serde(default)]
+    pub merge_directories: bool,
+    #[serde(default)]
+    pub numbered_backups: bool,
+    #[serde(default)]
+    pub backup_suffix: Option<String>,
+    #[serde(default)]
+    pub auto_rename_pattern: Option<String>,
+    #[serde(default)]
+    pub sort: Sort,
+    #[serde(default)]
+    pub reverse: bool,
+}
+
+impl FilesEnvironmentConfig {
+    pub fn backup_scheme(&self) -> BackupScheme {
+        match (self.numbered_backups, &self.backup_suffix) {
+            (true, _) => BackupScheme::Numbered,
+            (false, Some(suffix)) => BackupScheme::Simple {
+                suffix: suffix.clone(),
+            },
+            (false, None) => BackupScheme::default(),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -75,6 +178,28 @@ impl<'a> FilesEnvironment<'a> {
         })
     }
 
+    /// Returns true if all files are in their initial state
+    pub fn is_before(&self) -> bool {
+        self.files_environment_config
+            .before
+            .iter()
+            .all(|(before, _)| {
+                let full_before = self.get_full_path(before);
+                match before.ends_with('/') {
+                    true => Path::new(&full_before).is_dir(),
+                    false => read_to_string(&full_before).unwrap_or("".to_string()) == full_before,
+                }
+            })
+    }
+
+    /// Returns true if the path has the initial content of the `before` path
+    fn has_content_of(&self, path: &str, before: &str) -> bool {
+        match before.ends_with('/') {
+            true => Path::new(path).is_dir(),
+            false => read_to_string(path).unwrap_or("".to_string()) == self.get_full_path(before),
+        }
+    }
+
     pub fn is_after(&self) -> bool {
         for after_path in &self.files_environment_config.after {
             let after_path = self.get_full_path(after_path);
@@ -88,16 +213,12 @@ impl<'a> FilesEnvironment<'a> {
             }
         }
 
-        let all_renamed: BTreeSet<&String> = match self.files_environment_config.action_when_exists
-        {
-            ActionWhenExists::Overwrite => BTreeSet::from_iter(
-                self.files_environment_config
-                    .before
-                    .iter()
-                    .filter_map(|(_before, after)| after.as_ref()),
-            ),
-            _ => BTreeSet::default(),
-        };
+        let all_renamed: BTreeSet<&String> = BTreeSet::from_iter(
+            self.files_environment_config
+                .before
+                .iter()
+                .filter_map(|(_before, after)| after.as_ref()),
+        );
 
         for (before, after) in &self.files_environment_config.before {
             let full_before = self.get_full_path(before);
@@ -110,7 +231,7 @@ impl<'a> FilesEnvironment<'a> {
                     }
                     if !Path::new(&full_before).exists() {
                         false
-                    } else if before.chars().last().unwrap() != '/' {
+                    } else if !before.ends_with('/') {
                         // is not directory
                         read_to_string(&full_before).unwrap_or("".to_string()) == full_before
                     } else {
@@ -118,10 +239,14 @@ impl<'a> FilesEnvironment<'a> {
                     }
                 }
                 Some(after) => {
-                    // must be moved
+                    // must be moved or copied
                     let full_after = self.get_full_path(after);
-                    !Path::new(&full_before).exists()
-                        && read_to_string(&full_after).unwrap_or("".to_string()) == full_before
+                    let is_source_correct = all_renamed.contains(&before)
+                        || match self.files_environment_config.mode {
+                            Mode::Move => !Path::new(&full_before).exists(),
+                            _ => self.has_content_of(&full_before, before),
+                        };
+                    is_source_correct && self.has_content_of(&full_after, before)
                 }
             };
             if !is_correct {
@@ -1,9 +1,15 @@
 mod files_environment;
 
 use crate::files_environment::{FilesEnvironment, FilesEnvironmentConfig};
-use tfr::TemplateFileRenamer;
+use tfr::{
+    ActionWhenRenamedFilePathExists, BackupScheme, ConflictDecision, ConflictResolution, Journal,
+    MatchKind, MetadataFallback, OperationMode, RenameAction, RenameNotice, RenamePlan,
+    TemplateFileRenamer, TfrError, WhenIdentical,
+};
+use std::collections::HashSet;
 use std::io;
 use std::io::Read;
+use std::path::Path;
 
 mod integration_tests {
     use super::*;
@@ -21,7 +27,18 @@ mod integration_tests {
         let environment_config = read_environment_config(&config_path).unwrap();
         let files_environment = FilesEnvironment::new(&environment_config).unwrap();
 
-        let tfr = TemplateFileRenamer::new(environment_config.action_when_exists.clone().into());
+        let mut tfr =
+            TemplateFileRenamer::new(environment_config.action_when_exists.clone().into());
+        tfr.set_transactional(environment_config.transactional);
+        tfr.set_backup_scheme(environment_config.backup_scheme());
+        if let Some(auto_rename_pattern) = &environment_config.auto_rename_pattern {
+            tfr.set_auto_rename_pattern(auto_rename_pattern);
+        }
+        tfr.set_operation_mode(environment_config.mode.clone().into());
+        tfr.set_match_kind(environment_config.match_kind.clone().into());
+        tfr.set_merge_directories(environment_config.merge_directories);
+        tfr.set_sort_by(environment_config.sort.clone().into());
+        tfr.set_reverse_order(environment_config.reverse);
 
         let result = tfr.rename(
             &files_environment.get_full_path(&environment_config.input_template),
@@ -35,6 +52,393 @@ mod integration_tests {
         assert!(is_correct_status && files_environment.is_after())
     }
 
+    #[test]
+    fn plan_test() {
+        let environment_config = read_environment_config("tests/tests/plan.json").unwrap();
+        let files_environment = FilesEnvironment::new(&environment_config).unwrap();
+
+        let tfr = TemplateFileRenamer::new(environment_config.action_when_exists.clone().into());
+        let mut plan = tfr
+            .plan(
+                &files_environment.get_full_path(&environment_config.input_template),
+                &files_environment.get_full_path(&environment_config.output_template),
+            )
+            .unwrap();
+        assert!(!files_environment.is_after());
+
+        assert_eq!(plan.entries().len(), 3);
+        let action_of = |plan: &RenamePlan, source: &str| {
+            let source = files_environment.get_full_path(source);
+            plan.entries()
+                .iter()
+                .find(|entry| entry.source == source)
+                .map(|entry| entry.action)
+        };
+        assert_eq!(action_of(&plan, "in/a.txt"), Some(RenameAction::Rename));
+        assert_eq!(action_of(&plan, "in/b.txt"), Some(RenameAction::Skip));
+        assert_eq!(action_of(&plan, "in/c.tmp"), Some(RenameAction::Rename));
+        assert_eq!(plan.created_directories().len(), 2);
+
+        plan.retain(|entry| !entry.source.ends_with(".tmp"));
+        assert_eq!(
+            plan.created_directories(),
+            [files_environment.get_full_path("out/a")]
+        );
+
+        let serialized = serde_json::to_string(&plan).unwrap();
+        let deserialized: RenamePlan = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, plan);
+
+        tfr.execute(&deserialized).unwrap();
+        assert!(files_environment.is_after());
+    }
+
+    #[test]
+    fn collision_test() {
+        test_with_json_config("collision.json");
+
+        let environment_config = read_environment_config("tests/tests/collision.json").unwrap();
+        let files_environment = FilesEnvironment::new(&environment_config).unwrap();
+        let input_template = files_environment.get_full_path(&environment_config.input_template);
+        let output_template = files_environment.get_full_path(&environment_config.output_template);
+
+        let plan = TemplateFileRenamer::new(ActionWhenRenamedFilePathExists::Terminate)
+            .plan(&input_template, &output_template)
+            .unwrap();
+        match plan.check() {
+            Err(TfrError::DestinationCollision(destination, mut sources)) => {
+                sources.sort();
+                assert_eq!(destination, files_environment.get_full_path("out/ab1.txt"));
+                assert_eq!(
+                    sources,
+                    [
+                        files_environment.get_full_path("a_b1.txt"),
+                        files_environment.get_full_path("ab_1.txt")
+                    ]
+                );
+            }
+            _ => panic!("collision is not detected"),
+        }
+
+        let count = |plan: &RenamePlan, action: RenameAction| {
+            plan.entries()
+                .iter()
+                .filter(|entry| entry.action == action)
+                .count()
+        };
+        for rename_mod in [
+            ActionWhenRenamedFilePathExists::Skip,
+            ActionWhenRenamedFilePathExists::Overwrite,
+        ] {
+            let plan = TemplateFileRenamer::new(rename_mod)
+                .plan(&input_template, &output_template)
+                .unwrap();
+            assert!(plan.check().is_ok());
+            assert_eq!(count(&plan, RenameAction::Rename), 2);
+            assert_eq!(count(&plan, RenameAction::Skip), 1);
+        }
+    }
+
+    #[test]
+    fn swap_test() {
+        test_with_json_config("swap.json");
+    }
+
+    #[test]
+    fn chain_test() {
+        test_with_json_config("chain.json");
+    }
+
+    #[test]
+    fn retain_chain_link_test() {
+        let mut environment_config = read_environment_config("tests/tests/chain.json").unwrap();
+        environment_config.environment_name += "_retain";
+        let files_environment = FilesEnvironment::new(&environment_config).unwrap();
+        let full_path = |path: &str| files_environment.get_full_path(path);
+
+        let tfr = TemplateFileRenamer::new(ActionWhenRenamedFilePathExists::Terminate);
+        let mut plan = tfr
+            .plan(
+                &full_path(&environment_config.input_template),
+                &full_path(&environment_config.output_template),
+            )
+            .unwrap();
+        assert!(plan.check().is_ok());
+        plan.retain(|entry| !entry.source.ends_with("fxx.txt"));
+
+        assert_eq!(plan.conflicts().count(), 2);
+        assert!(matches!(plan.check(), Err(TfrError::ExistingPath(_, true))));
+        assert!(tfr.execute(&plan).is_err());
+        assert!(files_environment.is_before());
+    }
+
+    #[test]
+    fn rollback_test() {
+        test_with_json_config("rollback.json");
+
+        let environment_config = read_environment_config("tests/tests/rollback.json").unwrap();
+        let files_environment = FilesEnvironment::new(&environment_config).unwrap();
+
+        let mut tfr = TemplateFileRenamer::new(ActionWhenRenamedFilePathExists::Overwrite);
+        tfr.set_transactional(true);
+        let result = tfr.rename(
+            &files_environment.get_full_path(&environment_config.input_template),
+            &files_environment.get_full_path(&environment_config.output_template),
+        );
+        match result {
+            Err(TfrError::RolledBack(_, rollback_errors)) => assert!(rollback_errors.is_empty()),
+            _ => panic!("renaming is not rolled back"),
+        }
+        for created_directory in ["a", "b", "d"] {
+            assert!(!Path::new(&files_environment.get_full_path(created_directory)).exists());
+        }
+        assert!(files_environment.is_after());
+    }
+
+    #[test]
+    fn undo_test() {
+        let environment_config = read_environment_config("tests/tests/undo.json").unwrap();
+        let files_environment = FilesEnvironment::new(&environment_config).unwrap();
+        let input_template = files_environment.get_full_path(&environment_config.input_template);
+        let output_template = files_environment.get_full_path(&environment_config.output_template);
+        let mut journal = Journal::new(files_environment.get_full_path("state"));
+        journal.set_keep_overwritten(true);
+
+        let mut tfr = TemplateFileRenamer::new(ActionWhenRenamedFilePathExists::Overwrite);
+        tfr.set_journal(journal.clone());
+        tfr.rename(&input_template, &output_template).unwrap();
+        assert!(files_environment.is_after());
+        assert_eq!(journal.batches().unwrap().len(), 1);
+
+        let batch = journal.undo(None).unwrap();
+        assert_eq!(batch.renamed.len(), 2);
+        assert!(files_environment.is_before());
+        assert!(!Path::new(&files_environment.get_full_path("new")).exists());
+        assert!(journal.batches().unwrap().is_empty());
+        assert!(matches!(
+            journal.undo(None),
+            Err(TfrError::JournalBatchNotFound(None))
+        ));
+
+        tfr.rename(&input_template, &output_template).unwrap();
+        let changed_path = files_environment.get_full_path("new/after.txt");
+        std::fs::write(&changed_path, "changed").unwrap();
+        match journal.undo(None) {
+            Err(TfrError::ChangedSinceRename(path)) => assert_eq!(path, changed_path),
+            _ => panic!("changed file is undone"),
+        }
+        assert_eq!(journal.batches().unwrap().len(), 1);
+        assert!(Path::new(&files_environment.get_full_path("old/after.txt")).exists());
+    }
+
+    #[test]
+    fn undo_deleted_file_test() {
+        let mut environment_config = read_environment_config("tests/tests/undo.json").unwrap();
+        environment_config.environment_name += "_deleted";
+        let files_environment = FilesEnvironment::new(&environment_config).unwrap();
+        let full_path = |path: &str| files_environment.get_full_path(path);
+        let journal = Journal::new(full_path("state"));
+
+        let mut tfr = TemplateFileRenamer::new(ActionWhenRenamedFilePathExists::Overwrite);
+        tfr.set_journal(journal.clone());
+        tfr.rename(&full_path("*_before.txt"), &full_path("#1/after.txt"))
+            .unwrap();
+        assert!(files_environment.is_after());
+        assert!(!Path::new(&full_path("state/saved")).exists());
+
+        let batch = journal.undo(None).unwrap();
+        let deleted: Vec<&str> = batch.deleted_files().collect();
+        assert_eq!(deleted, vec![full_path("old/after.txt")]);
+        assert!(Path::new(&full_path("new_before.txt")).exists());
+        assert!(Path::new(&full_path("old_before.txt")).exists());
+        assert!(!Path::new(&full_path("old/after.txt")).exists());
+        assert!(journal.batches().unwrap().is_empty());
+    }
+
+    #[test]
+    fn batch_id_test() {
+        use chrono::{Duration, Local};
+
+        let mut environment_config = read_environment_config("tests/tests/undo.json").unwrap();
+        environment_config.environment_name += "_batch_id";
+        let files_environment = FilesEnvironment::new(&environment_config).unwrap();
+        let full_path = |path: &str| files_environment.get_full_path(path);
+        let mut journal = Journal::new(full_path("state"));
+        journal.set_max_batches(100_000);
+
+        // Batches without saved files take every id of the next 10 seconds
+        let now = Local::now();
+        let taken: HashSet<String> = (0..10_000)
+            .map(|ms| (now + Duration::milliseconds(ms)).format("%Y%m%d-%H%M%S%.3f").to_string())
+            .collect();
+        let batches: String = taken
+            .iter()
+            .map(|id| {
+                let batch = serde_json::json!({
+                    "id": id, "time": "", "renamed": [], "operations": [], "fingerprints": []
+                });
+                format!("{batch}\n")
+            })
+            .collect();
+        std::fs::create_dir(full_path("state")).unwrap();
+        std::fs::write(full_path("state/journal.jsonl"), batches).unwrap();
+
+        let mut tfr = TemplateFileRenamer::new(ActionWhenRenamedFilePathExists::Terminate);
+        tfr.set_journal(journal.clone());
+        tfr.rename(&full_path("ot*.txt"), &full_path("x#1.txt"))
+            .unwrap();
+        let batches = journal.batches().unwrap();
+        assert_eq!(batches.len(), taken.len() + 1);
+        assert!(!taken.contains(&batches.last().unwrap().id));
+    }
+
+    #[test]
+    fn journal_limit_test() {
+        let environment_config = read_environment_config("tests/tests/journal_limit.json").unwrap();
+        let files_environment = FilesEnvironment::new(&environment_config).unwrap();
+        let full_path = |path: &str| files_environment.get_full_path(path);
+        let mut journal = Journal::new(full_path("state"));
+        journal.set_max_batches(1);
+        journal.set_keep_overwritten(true);
+
+        let mut tfr = TemplateFileRenamer::new(ActionWhenRenamedFilePathExists::Overwrite);
+        tfr.set_journal(journal.clone());
+        tfr.rename(&full_path("x_*.txt"), &full_path("y_#1.txt"))
+            .unwrap();
+        let saved = std::fs::read_dir(full_path("state/saved")).unwrap().count();
+        assert_eq!(saved, 1);
+        tfr.rename(&full_path("y_*.txt"), &full_path("z_#1.txt"))
+            .unwrap();
+        let renamed = std::fs::read_to_string(full_path("z_1.txt")).unwrap();
+        assert_eq!(renamed, full_path("x_1.txt"));
+
+        let batches = journal.batches().unwrap();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].renamed[0].1, full_path("z_1.txt"));
+        let saved = std::fs::read_dir(full_path("state/saved")).unwrap().count();
+        assert_eq!(saved, 0);
+        journal.undo(None).unwrap();
+        assert!(Path::new(&full_path("y_1.txt")).exists());
+        assert!(journal.undo(None).is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    #[ignore = "needs /dev/shm on another filesystem, run with --ignored"]
+    fn cross_device_test() {
+        use std::cell::Cell;
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+        use std::time::{Duration, SystemTime};
+
+        let environment_config = read_environment_config("tests/tests/cross_device.json").unwrap();
+        let files_environment = FilesEnvironment::new(&environment_config).unwrap();
+        let source = files_environment.get_full_path("before_1.txt");
+
+        let other_filesystem = Path::new("/dev/shm");
+        let is_other_device = |path: &Path| {
+            path.metadata().map(|metadata| metadata.dev()).ok()
+                != Path::new(&source).metadata().map(|metadata| metadata.dev()).ok()
+        };
+        assert!(
+            other_filesystem.is_dir() && is_other_device(other_filesystem),
+            "/dev/shm must be located on another filesystem than the test files"
+        );
+        let output_directory = other_filesystem.join("tfr-test-cross-device");
+        let _ = std::fs::remove_dir_all(&output_directory);
+
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        std::fs::File::options()
+            .write(true)
+            .open(&source)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        std::fs::set_permissions(&source, std::fs::Permissions::from_mode(0o640)).unwrap();
+
+        let copies = Cell::new(0);
+        let mut tfr = TemplateFileRenamer::new(ActionWhenRenamedFilePathExists::Terminate);
+        tfr.set_notice_handler(|notice: RenameNotice| {
+            if let RenameNotice::CrossDeviceCopy { .. } = notice {
+                copies.set(copies.get() + 1)
+            }
+        });
+        let result = tfr.rename(
+            &files_environment.get_full_path(&environment_config.input_template),
+            &output_directory
+                .join(&environment_config.output_template)
+                .to_string_lossy(),
+        );
+
+        let destination = output_directory.join("1.txt");
+        let content = std::fs::read_to_string(&destination);
+        let metadata = destination.metadata();
+        let _ = std::fs::remove_dir_all(&output_directory);
+
+        assert!(result.is_ok());
+        assert_eq!(copies.get(), 2);
+        assert!(!Path::new(&source).exists());
+        assert_eq!(content.unwrap(), source);
+        let metadata = metadata.unwrap();
+        assert_eq!(metadata.modified().unwrap(), modified);
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o640);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    #[ignore = "needs /dev/shm on another filesystem, run with --ignored"]
+    fn cross_device_directory_test() {
+        use std::cell::Cell;
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+        let environment_config =
+            read_environment_config("tests/tests/cross_device_directory.json").unwrap();
+        let files_environment = FilesEnvironment::new(&environment_config).unwrap();
+        let full_path = |path: &str| files_environment.get_full_path(path);
+        let source = full_path("proj_a");
+
+        let other_filesystem = Path::new("/dev/shm");
+        let is_other_device = |path: &Path| {
+            path.metadata().map(|metadata| metadata.dev()).ok()
+                != Path::new(&source).metadata().map(|metadata| metadata.dev()).ok()
+        };
+        assert!(
+            other_filesystem.is_dir() && is_other_device(other_filesystem),
+            "/dev/shm must be located on another filesystem than the test files"
+        );
+        let output_directory = other_filesystem.join("tfr-test-cross-device-directory");
+        let _ = std::fs::remove_dir_all(&output_directory);
+        let permissions = std::fs::Permissions::from_mode(0o750);
+        std::fs::set_permissions(full_path("proj_a/sub"), permissions).unwrap();
+
+        let copies = Cell::new(0);
+        let mut tfr = TemplateFileRenamer::new(ActionWhenRenamedFilePathExists::Terminate);
+        tfr.set_match_kind(MatchKind::Directories);
+        tfr.set_notice_handler(|notice: RenameNotice| {
+            if let RenameNotice::CrossDeviceCopy { .. } = notice {
+                copies.set(copies.get() + 1)
+            }
+        });
+        let result = tfr.rename(
+            &full_path(&environment_config.input_template),
+            &output_directory
+                .join(&environment_config.output_template)
+                .to_string_lossy(),
+        );
+
+        let destination = output_directory.join("a");
+        let file = std::fs::read_to_string(destination.join("file.txt"));
+        let inner = std::fs::read_to_string(destination.join("sub/inner.txt"));
+        let sub_metadata = destination.join("sub").metadata();
+        let _ = std::fs::remove_dir_all(&output_directory);
+
+        assert!(result.is_ok());
+        assert_eq!(copies.get(), 1);
+        assert!(!Path::new(&source).exists());
+        assert_eq!(file.unwrap(), full_path("proj_a/file.txt"));
+        assert_eq!(inner.unwrap(), full_path("proj_a/sub/inner.txt"));
+        assert_eq!(sub_metadata.unwrap().permissions().mode() & 0o777, 0o750);
+    }
+
     #[test]
     fn simple_test() {
         test_with_json_config("simple.json");
@@ -55,16 +459,41 @@ mod integration_tests {
         test_with_json_config("flag_before_last_part.json");
     }
 
+    #[test]
+    fn recursive_capture_test() {
+        test_with_json_config("recursive_capture.json");
+    }
+
     #[test]
     fn escaped_asterisk_test() {
         test_with_json_config("escaped_asterisk.json");
     }
 
+    #[test]
+    fn directory_captures_test() {
+        test_with_json_config("directory_captures.json");
+    }
+
     #[test]
     fn directory_test() {
         test_with_json_config("directory.json");
     }
 
+    #[test]
+    fn match_directories_test() {
+        test_with_json_config("directory_all.json");
+        test_with_json_config("nested_directories.json");
+        test_with_json_config("existing_directory.json");
+        test_with_json_config("existing_directory_skip.json");
+        test_with_json_config("copy_directory.json");
+    }
+
+    #[test]
+    fn merge_directories_test() {
+        test_with_json_config("merge_directories.json");
+        test_with_json_config("merge_directories_skip.json");
+    }
+
     #[test]
     fn output_template_with_hash_test() {
         test_with_json_config("output_template_with_hash.json");
@@ -88,9 +517,9 @@ mod integration_tests {
 
     #[test]
     fn templates_error_test() {
-        test_with_json_config("templates_error/asterisk_before_last_part.json");
         test_with_json_config("templates_error/double_asterisk.json");
         test_with_json_config("templates_error/captures_not_covered_by_flags.json");
+        test_with_json_config("templates_error/counter_like_literal.json");
     }
 
     #[test]
@@ -102,4 +531,448 @@ mod integration_tests {
     fn overwrite_when_exists_test() {
         test_with_json_config("overwrite_when_exists.json");
     }
+
+    #[test]
+    fn backup_when_exists_test() {
+        test_with_json_config("backup_when_exists.json");
+    }
+
+    #[test]
+    fn backup_taken_by_batch_test() {
+        test_with_json_config("backup_taken.json");
+        test_with_json_config("backup_taken_reverse.json");
+    }
+
+    #[test]
+    fn numbered_backup_test() {
+        use std::cell::RefCell;
+
+        test_with_json_config("backup_suffix.json");
+        test_with_json_config("numbered_backup.json");
+
+        let environment_config =
+            read_environment_config("tests/tests/numbered_backup.json").unwrap();
+        let files_environment = FilesEnvironment::new(&environment_config).unwrap();
+        let backups = RefCell::new(vec![]);
+        let mut tfr = TemplateFileRenamer::new(ActionWhenRenamedFilePathExists::Backup);
+        tfr.set_backup_scheme(BackupScheme::Numbered);
+        tfr.set_notice_handler(|notice: RenameNotice| {
+            if let RenameNotice::BackedUp { path, backup } = notice {
+                backups
+                    .borrow_mut()
+                    .push((path.to_string(), backup.to_string()))
+            }
+        });
+        tfr.rename(
+            &files_environment.get_full_path(&environment_config.input_template),
+            &files_environment.get_full_path(&environment_config.output_template),
+        )
+        .unwrap();
+
+        assert_eq!(
+            *backups.borrow(),
+            [(
+                files_environment.get_full_path("after_1.txt"),
+                files_environment.get_full_path("after_1.txt.~3~")
+            )]
+        );
+    }
+
+    #[test]
+    fn auto_rename_test() {
+        test_with_json_config("auto_rename.json");
+        test_with_json_config("auto_rename_pattern.json");
+    }
+
+    #[test]
+    fn ask_test() {
+        let environment_config = read_environment_config("tests/tests/ask.json").unwrap();
+        let files_environment = FilesEnvironment::new(&environment_config).unwrap();
+        let input_template = files_environment.get_full_path(&environment_config.input_template);
+        let output_template = files_environment.get_full_path(&environment_config.output_template);
+
+        // Nothing is renamed if any conflict is aborted
+        let mut tfr = TemplateFileRenamer::new(ActionWhenRenamedFilePathExists::Ask);
+        tfr.set_conflict_handler(|_, _| ConflictDecision::once(ConflictResolution::Abort));
+        assert!(matches!(
+            tfr.rename(&input_template, &output_template),
+            Err(TfrError::Aborted)
+        ));
+        assert!(files_environment.is_before());
+
+        // The handler is not called after the decision applied to all
+        let calls = std::cell::Cell::new(0);
+        let mut tfr = TemplateFileRenamer::new(ActionWhenRenamedFilePathExists::Ask);
+        tfr.set_conflict_handler(|_, _| {
+            calls.set(calls.get() + 1);
+            ConflictDecision::for_all(ConflictResolution::Skip)
+        });
+        let plan = tfr.plan(&input_template, &output_template).unwrap();
+        assert_eq!(calls.get(), 1);
+        assert_eq!(plan.executed_entries().count(), 1);
+
+        let mut tfr = TemplateFileRenamer::new(ActionWhenRenamedFilePathExists::Ask);
+        tfr.set_conflict_handler(|source, _| {
+            ConflictDecision::once(match Path::new(source).file_name().unwrap().to_str() {
+                Some("a.txt") => ConflictResolution::Overwrite,
+                Some("b.txt") => ConflictResolution::Skip,
+                _ => ConflictResolution::Rename,
+            })
+        });
+        tfr.rename(&input_template, &output_template).unwrap();
+        assert!(files_environment.is_after());
+    }
+
+    #[test]
+    fn date_test() {
+        use chrono::{Local, TimeZone};
+
+        let environment_config = read_environment_config("tests/tests/date.json").unwrap();
+        let files_environment = FilesEnvironment::new(&environment_config).unwrap();
+        let full_path = |path: &str| files_environment.get_full_path(path);
+        for (path, year, month) in [("in/a.txt", 2023, 5), ("in/b.txt", 2021, 12)] {
+            let time = Local.with_ymd_and_hms(year, month, 7, 12, 0, 0).unwrap();
+            std::fs::File::options()
+                .write(true)
+                .open(full_path(path))
+                .unwrap()
+                .set_modified(time.into())
+                .unwrap();
+        }
+
+        let tfr = TemplateFileRenamer::new(ActionWhenRenamedFilePathExists::Terminate);
+        tfr.rename(
+            &full_path(&environment_config.input_template),
+            &full_path(&environment_config.output_template),
+        )
+        .unwrap();
+        assert!(files_environment.is_after());
+    }
+
+    /// JPEG with the EXIF header in little-endian byte order, IFD0 has `Model` and `DateTime` tags
+    fn exif_jpeg(model: &str, date: &str) -> Vec<u8> {
+        let model = format!("{model}\0");
+        let date = format!("{date}\0");
+        let model_offset = 8 + 2 + 2 * 12 + 4;
+        let date_offset = model_offset + model.len();
+        let mut tiff = b"II*\0".to_vec();
+        tiff.extend(8u32.to_le_bytes());
+        tiff.extend(2u16.to_le_bytes());
+        let entries = [(0x0110u16, &model, model_offset), (0x0132, &date, date_offset)];
+        for (tag, value, offset) in entries {
+            tiff.extend(tag.to_le_bytes());
+            tiff.extend(2u16.to_le_bytes());
+            tiff.extend((value.len() as u32).to_le_bytes());
+            tiff.extend((offset as u32).to_le_bytes());
+        }
+        tiff.extend(0u32.to_le_bytes());
+        tiff.extend(model.as_bytes());
+        tiff.extend(date.as_bytes());
+
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        jpeg.extend((tiff.len() as u16 + 8).to_be_bytes());
+        jpeg.extend(b"Exif\0\0");
+        jpeg.extend(tiff);
+        jpeg.extend([0xFF, 0xD9]);
+        jpeg
+    }
+
+    #[test]
+    fn exif_test() {
+        let environment_config = read_environment_config("tests/tests/exif.json").unwrap();
+        let input_template = &environment_config.input_template;
+        let output_template = &environment_config.output_template;
+        for fallback in [
+            MetadataFallback::Skip,
+            MetadataFallback::Default("unknown".to_string()),
+        ] {
+            let files_environment = FilesEnvironment::new(&environment_config).unwrap();
+            let full_path = |path: &str| files_environment.get_full_path(path);
+            let jpeg = exif_jpeg("X100", "2023:05:07 13:04:05");
+            std::fs::write(full_path("in/camera.jpg"), &jpeg).unwrap();
+
+            let mut tfr = TemplateFileRenamer::new(ActionWhenRenamedFilePathExists::Terminate);
+            tfr.set_metadata_fallback(fallback.clone());
+            tfr.rename(&full_path(input_template), &full_path(output_template))
+                .unwrap();
+
+            let camera = std::fs::read(full_path("out/X100/2023_camera.jpg")).unwrap();
+            assert_eq!(camera, jpeg);
+            let is_skipped = fallback == MetadataFallback::Skip;
+            assert_eq!(Path::new(&full_path("in/scan.jpg")).exists(), is_skipped);
+            assert_eq!(
+                Path::new(&full_path("out/unknown/unknown_scan.jpg")).exists(),
+                !is_skipped
+            );
+        }
+    }
+
+    fn id3v2_mp3(frames: &[(&str, &str)]) -> Vec<u8> {
+        let mut body = vec![];
+        for (id, text) in frames {
+            body.extend(id.as_bytes());
+            body.extend((text.len() as u32 + 1).to_be_bytes());
+            body.extend([0, 0, 3]);
+            body.extend(text.as_bytes());
+        }
+        let size = [21, 14, 7, 0].map(|shift| ((body.len() >> shift) & 0x7F) as u8);
+        let mut mp3 = [b"ID3".as_slice(), &[3, 0, 0], &size, &body].concat();
+        mp3.extend([0xFF, 0xFB, 0x90, 0x00]);
+        mp3
+    }
+
+    #[test]
+    fn audio_tags_test() {
+        let environment_config = read_environment_config("tests/tests/audio_tags.json").unwrap();
+        let files_environment = FilesEnvironment::new(&environment_config).unwrap();
+        let full_path = |path: &str| files_environment.get_full_path(path);
+        let frames = [("TPE1", "AC/DC"), ("TALB", "Live"), ("TRCK", "3/12"), ("TIT2", "What?")];
+        let mp3 = id3v2_mp3(&frames);
+        std::fs::write(full_path("in/song.mp3"), &mp3).unwrap();
+
+        let tfr = TemplateFileRenamer::new(ActionWhenRenamedFilePathExists::Terminate);
+        tfr.rename(
+            &full_path(&environment_config.input_template),
+            &full_path(&environment_config.output_template),
+        )
+        .unwrap();
+
+        let song = std::fs::read(full_path("out/AC_DC/Live/03 - What_.mp3")).unwrap();
+        assert_eq!(song, mp3);
+        assert!(Path::new(&full_path("in/untagged.mp3")).exists());
+    }
+
+    #[test]
+    fn exif_date_folders_test() {
+        let environment_config =
+            read_environment_config("tests/tests/exif_date_folders.json").unwrap();
+        let files_environment = FilesEnvironment::new(&environment_config).unwrap();
+        let full_path = |path: &str| files_environment.get_full_path(path);
+        let jpeg = exif_jpeg("X/100", "2023:05:07 13:04:05");
+        std::fs::write(full_path("in/camera.jpg"), &jpeg).unwrap();
+
+        let tfr = TemplateFileRenamer::new(ActionWhenRenamedFilePathExists::Terminate);
+        tfr.rename(
+            &full_path(&environment_config.input_template),
+            &full_path(&environment_config.output_template),
+        )
+        .unwrap();
+
+        let camera = std::fs::read(full_path("out/2023/05/X_100_camera.jpg")).unwrap();
+        assert_eq!(camera, jpeg);
+    }
+
+    #[test]
+    fn when_identical_test() {
+        let environment_config = read_environment_config("tests/tests/identical.json").unwrap();
+        for when_identical in [WhenIdentical::Skip, WhenIdentical::RemoveSource] {
+            let files_environment = FilesEnvironment::new(&environment_config).unwrap();
+            let full_path = |path: &str| files_environment.get_full_path(path);
+            std::fs::write(full_path("in/same.txt"), "content").unwrap();
+            std::fs::write(full_path("out/same.txt"), "content").unwrap();
+
+            let mut tfr = TemplateFileRenamer::new(ActionWhenRenamedFilePathExists::Overwrite);
+            tfr.set_when_identical(when_identical);
+            tfr.rename(
+                &full_path(&environment_config.input_template),
+                &full_path(&environment_config.output_template),
+            )
+            .unwrap();
+
+            assert_eq!(
+                Path::new(&full_path("in/same.txt")).exists(),
+                when_identical == WhenIdentical::Skip
+            );
+            assert_eq!(
+                std::fs::read_to_string(full_path("out/same.txt")).unwrap(),
+                "content"
+            );
+            assert!(!Path::new(&full_path("in/different.txt")).exists());
+            assert_eq!(
+                std::fs::read_to_string(full_path("out/different.txt")).unwrap(),
+                full_path("in/different.txt")
+            );
+        }
+    }
+
+    #[test]
+    fn conditional_overwrite_test() {
+        use std::time::{Duration, SystemTime};
+
+        let environment_config =
+            read_environment_config("tests/tests/conditional_overwrite.json").unwrap();
+        for rename_mod in [
+            ActionWhenRenamedFilePathExists::OverwriteIfNewer,
+            ActionWhenRenamedFilePathExists::OverwriteIfLarger,
+        ] {
+            let files_environment = FilesEnvironment::new(&environment_config).unwrap();
+            let full_path = |path: &str| files_environment.get_full_path(path);
+            // `in/a.txt` is newer and larger than `out/a.txt`, `in/b.txt` is older and smaller than `out/b.txt`
+            for (path, content, age) in [
+                ("in/a.txt", "large", 0),
+                ("in/b.txt", "s", 2000),
+                ("out/a.txt", "s", 1000),
+                ("out/b.txt", "large", 1000),
+            ] {
+                std::fs::write(full_path(path), content).unwrap();
+                std::fs::File::options()
+                    .write(true)
+                    .open(full_path(path))
+                    .unwrap()
+                    .set_modified(SystemTime::now() - Duration::from_secs(age))
+                    .unwrap();
+            }
+
+            let tfr = TemplateFileRenamer::new(rename_mod);
+            tfr.rename(
+                &full_path(&environment_config.input_template),
+                &full_path(&environment_config.output_template),
+            )
+            .unwrap();
+
+            let content = |path: &str| std::fs::read_to_string(full_path(path)).unwrap();
+            assert!(!Path::new(&full_path("in/a.txt")).exists());
+            assert_eq!(content("out/a.txt"), "large");
+            assert_eq!(content("in/b.txt"), "s");
+            assert_eq!(content("out/b.txt"), "large");
+        }
+    }
+
+    #[test]
+    fn case_modifiers_test() {
+        test_with_json_config("case_modifiers.json");
+    }
+
+    #[test]
+    fn counter_test() {
+        test_with_json_config("counter.json");
+    }
+
+    #[test]
+    fn sort_test() {
+        test_with_json_config("natural_sort.json");
+        test_with_json_config("size_sort.json");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ctime_sort_test() {
+        use std::os::unix::fs::PermissionsExt;
+        use std::time::{Duration, SystemTime};
+
+        let environment_config = read_environment_config("tests/tests/ctime_sort.json").unwrap();
+        let files_environment = FilesEnvironment::new(&environment_config).unwrap();
+        let full_path = |path: &str| files_environment.get_full_path(path);
+
+        // Modification times follow the names, inode change times go in the reverse order
+        for (idx, path) in ["in/a.txt", "in/b.txt", "in/c.txt"].iter().enumerate() {
+            let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1000 * idx as u64);
+            let file = std::fs::File::options().write(true).open(full_path(path));
+            file.unwrap().set_modified(modified).unwrap();
+        }
+        for path in ["in/c.txt", "in/b.txt", "in/a.txt"] {
+            std::thread::sleep(Duration::from_millis(20));
+            let permissions = std::fs::Permissions::from_mode(0o640);
+            std::fs::set_permissions(full_path(path), permissions).unwrap();
+        }
+
+        let mut tfr = TemplateFileRenamer::new(ActionWhenRenamedFilePathExists::Terminate);
+        tfr.set_sort_by(environment_config.sort.clone().into());
+        tfr.rename(
+            &full_path(&environment_config.input_template),
+            &full_path(&environment_config.output_template),
+        )
+        .unwrap();
+        assert!(files_environment.is_after());
+    }
+
+    #[test]
+    fn number_format_test() {
+        test_with_json_config("number_format.json");
+        test_with_json_config("not_numeric_capture.json");
+    }
+
+    #[test]
+    fn copy_test() {
+        test_with_json_config("copy.json");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn hard_link_test() {
+        use std::os::unix::fs::MetadataExt;
+
+        test_with_json_config("hard_link.json");
+
+        let environment_config = read_environment_config("tests/tests/hard_link.json").unwrap();
+        let files_environment = FilesEnvironment::new(&environment_config).unwrap();
+        let mut tfr = TemplateFileRenamer::new(ActionWhenRenamedFilePathExists::Terminate);
+        tfr.set_operation_mode(OperationMode::HardLink);
+        tfr.rename(
+            &files_environment.get_full_path(&environment_config.input_template),
+            &files_environment.get_full_path(&environment_config.output_template),
+        )
+        .unwrap();
+
+        let inode = |path: &str| {
+            let metadata = std::fs::metadata(files_environment.get_full_path(path)).unwrap();
+            (metadata.dev(), metadata.ino())
+        };
+        assert_eq!(
+            inode("photos/canon/IMG_1.jpg"),
+            inode("views/by_camera/canon/1.jpg")
+        );
+    }
+
+    #[test]
+    fn symlink_test() {
+        test_with_json_config("symlink.json");
+
+        let environment_config = read_environment_config("tests/tests/symlink.json").unwrap();
+        let files_environment = FilesEnvironment::new(&environment_config).unwrap();
+        let mut tfr = TemplateFileRenamer::new(ActionWhenRenamedFilePathExists::Terminate);
+        tfr.set_operation_mode(OperationMode::SymLink { relative: false });
+        tfr.rename(
+            &files_environment.get_full_path(&environment_config.input_template),
+            &files_environment.get_full_path(&environment_config.output_template),
+        )
+        .unwrap();
+
+        let link = files_environment.get_full_path("views/by_camera/canon/1.jpg");
+        assert!(std::fs::read_link(link).unwrap().is_absolute());
+    }
+
+    #[test]
+    fn relative_symlink_test() {
+        test_with_json_config("relative_symlink.json");
+
+        let environment_config =
+            read_environment_config("tests/tests/relative_symlink.json").unwrap();
+        let files_environment = FilesEnvironment::new(&environment_config).unwrap();
+        let mut tfr = TemplateFileRenamer::new(ActionWhenRenamedFilePathExists::Terminate);
+        tfr.set_operation_mode(OperationMode::SymLink { relative: true });
+        tfr.rename(
+            &files_environment.get_full_path(&environment_config.input_template),
+            &files_environment.get_full_path(&environment_config.output_template),
+        )
+        .unwrap();
+
+        let link = files_environment.get_full_path("views/by_camera/canon/1.jpg");
+        assert_eq!(
+            std::fs::read_link(&link).unwrap(),
+            Path::new("../../../photos/canon/IMG_1.jpg")
+        );
+
+        // Links remain valid when the whole tree is moved
+        let root = files_environment.get_full_path("");
+        let moved_root = format!("{}_moved", root.trim_end_matches('/'));
+        std::fs::rename(&root, &moved_root).unwrap();
+        let content =
+            std::fs::read_to_string(format!("{moved_root}/views/by_camera/canon/1.jpg"));
+        std::fs::rename(&moved_root, &root).unwrap();
+        assert_eq!(
+            content.unwrap(),
+            files_environment.get_full_path("photos/canon/IMG_1.jpg")
+        );
+    }
 }